@@ -1,29 +1,67 @@
+use std::env;
+use std::fmt;
 use std::io::{self, BufRead};
+use std::process;
 use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
+enum ParseError {
+	Width { expected: usize, found: usize },
+	Char(char),
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Width { expected, found } => write!(f, "expected {} bits, found {}", expected, found),
+			Self::Char(c) => write!(f, "invalid character {:?}", c),
+		}
+	}
+}
+
+// Bit `i` of the string (counted from the left) is stored at `N - 1 - i`, so the packed value is the number itself
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct BitString<const N: usize> {
-	bits: [u8; N],
+	bits: u64,
 }
 
 impl<const N: usize> BitString<N> {
-	fn new(init_val: u8) -> Self {
+	fn new() -> Self {
 		Self {
-			bits: [init_val; N],
+			bits: 0,
 		}
 	}
+
+	fn get(self, i: usize) -> bool {
+		self.bits >> (N - 1 - i) & 1 == 1
+	}
+
+	fn set(&mut self, i: usize) {
+		self.bits |= 1 << (N - 1 - i);
+	}
+
+	fn to_int(self) -> u64 {
+		self.bits
+	}
 }
 
 impl<const N: usize> FromStr for BitString<N> {
-	type Err = ();
+	type Err = ParseError;
 
 	fn from_str(input: &str) -> Result<Self, Self::Err> {
-		let mut chars = input.chars();
-		let mut out = Self::new(0);
+		let found = input.chars().count();
+		if found != N {
+			return Err(ParseError::Width { expected: N, found });
+		}
+
+		let mut out = Self::new();
 
-		for v in out.bits.iter_mut() {
-			if chars.next().unwrap() == '1' {
-				*v = 1;
+		for c in input.chars() {
+			out.bits <<= 1;
+			match c {
+				'0' => {},
+				'1' => out.bits |= 1,
+				_ => return Err(ParseError::Char(c)),
 			}
 		}
 
@@ -31,19 +69,115 @@ impl<const N: usize> FromStr for BitString<N> {
 	}
 }
 
-impl<const N: usize> ToString for BitString<N> {
-	fn to_string(&self) -> String {
-		let mut out: String = String::new();
+impl<const N: usize> fmt::Display for BitString<N> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:0width$b}", self.bits, width = N)
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Common {
+	Most,
+	Least,
+}
 
-		for v in self.bits.iter() {
-			if *v == 1 {
-				out += "1"
-			} else {
-				out += "0"
-			}
+#[derive(Debug, Copy, Clone)]
+struct Criteria {
+	common: Common,
+	tie: bool,
+}
+
+impl Criteria {
+	const fn new(common: Common, tie: bool) -> Self {
+		Self {
+			common,
+			tie,
 		}
+	}
 
-		out
+	// Returns the selected bit and whether it was decided by the tie rule
+	fn select(self, zeros: usize, ones: usize) -> (bool, bool) {
+		if zeros == ones {
+			return (self.tie, true);
+		}
+
+		match self.common {
+			Common::Most => (ones > zeros, false),
+			Common::Least => (ones < zeros, false),
+		}
+	}
+}
+
+impl FromStr for Criteria {
+	type Err = ();
+
+	// Format: `most:1`, `least:0`, ...
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (common, tie) = input.split_once(':').ok_or(())?;
+
+		let common = match common {
+			"most" => Common::Most,
+			"least" => Common::Least,
+			_ => return Err(()),
+		};
+
+		let tie = match tie {
+			"0" => false,
+			"1" => true,
+			_ => return Err(()),
+		};
+
+		Ok(Self::new(common, tie))
+	}
+}
+
+#[derive(Debug)]
+struct Rules {
+	gamma: Criteria,
+	epsilon: Criteria,
+	oxygen: Criteria,
+	co2: Criteria,
+}
+
+impl Rules {
+	fn new() -> Self {
+		Self {
+			gamma: Criteria::new(Common::Most, true),
+			epsilon: Criteria::new(Common::Least, false),
+			oxygen: Criteria::new(Common::Most, true),
+			co2: Criteria::new(Common::Least, false),
+		}
+	}
+
+	// Format: `<gamma|epsilon|oxygen|co2>=<criteria>`
+	fn set(&mut self, arg: &str) -> Result<(), ()> {
+		let (name, criteria) = arg.split_once('=').ok_or(())?;
+		let criteria = Criteria::from_str(criteria)?;
+
+		match name {
+			"gamma" => self.gamma = criteria,
+			"epsilon" => self.epsilon = criteria,
+			"oxygen" => self.oxygen = criteria,
+			"co2" => self.co2 = criteria,
+			_ => return Err(()),
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Debug)]
+struct Rating<const N: usize> {
+	value: BitString<N>,
+	ties: Vec<usize>,
+}
+
+impl<const N: usize> Rating<N> {
+	fn new() -> Self {
+		Self {
+			value: BitString::new(),
+			ties: vec![],
+		}
 	}
 }
 
@@ -62,73 +196,149 @@ impl<const N: usize> State<N> {
 	}
 
 	fn push(&mut self, input: BitString<N>) {
-		for (i, v) in self.one_count.iter_mut().enumerate() {
-			*v += input.bits[i] as u32;
+		// Only visits the set bits, so the cost is the popcount of the input
+		let mut bits = input.bits;
+		while bits != 0 {
+			self.one_count[N - 1 - bits.trailing_zeros() as usize] += 1;
+			bits &= bits - 1;
 		}
 
 		self.values.push(input);
 	}
 
-	fn calculate_oxygen(&self) -> BitString<N> {
-		let mut oxygen_list: Vec<&BitString<N>> = self.values.iter().collect();
+	// Gamma and epsilon only look at the column counts, no values are discarded
+	fn column(&self, criteria: Criteria) -> Rating<N> {
+		let mut out = Rating::new();
 
 		for i in 0..N {
-			if oxygen_list.len() == 1 {
-				return *oxygen_list[0];
-			}
+			let ones = self.one_count[i] as usize;
+			let (bit, tie) = criteria.select(self.values.len() - ones, ones);
 
-			let one_count = oxygen_list.iter().filter(|value| value.bits[i] == 1).count();
-			let criteria = if (one_count as f32) >= oxygen_list.len() as f32 / 2f32 { 1 } else { 0 };
-			oxygen_list.retain(|value| value.bits[i] == criteria);
+			if bit {
+				out.value.set(i);
+			}
+			if tie {
+				out.ties.push(i);
+			}
 		}
 
-		*oxygen_list[0]
+		out
 	}
 
-	fn calculate_co2(&self) -> BitString<N> {
-		let mut co2_list: Vec<&BitString<N>> = self.values.iter().collect();
+	// Requires `values` to be sorted: every value in `range` shares the bits before `i`,
+	// so the ones at bit `i` form a contiguous tail that can be found by binary search
+	fn filter(&self, criteria: Criteria) -> Rating<N> {
+		let mut out = Rating::new();
+		let mut range = &self.values[..];
 
 		for i in 0..N {
-			if co2_list.len() == 1 {
-				return *co2_list[0];
+			if range.len() == 1 {
+				break;
 			}
 
-			let one_count = co2_list.iter().filter(|value| value.bits[i] == 1).count();
-			let criteria = if (one_count as f32) < co2_list.len() as f32 / 2f32 { 1 } else { 0 };
-			co2_list.retain(|value| value.bits[i] == criteria);
+			let split = range.partition_point(|value| !value.get(i));
+			let (zeros, ones) = range.split_at(split);
+
+			range = if zeros.is_empty() {
+				ones
+			} else if ones.is_empty() {
+				zeros
+			} else {
+				let (bit, tie) = criteria.select(zeros.len(), ones.len());
+				if tie {
+					out.ties.push(i);
+				}
+
+				if bit { ones } else { zeros }
+			};
 		}
 
-		*co2_list[0]
+		out.value = range[0];
+		out
 	}
 
-	fn output(&self) -> (BitString<N>, BitString<N>, BitString<N>, BitString<N>) {
-		let mut gamma = BitString::<N>::new(0);
-		let mut epsilon = BitString::<N>::new(0);
+	fn output(&mut self, rules: &Rules) -> (Rating<N>, Rating<N>, Rating<N>, Rating<N>) {
+		let gamma = self.column(rules.gamma);
+		let epsilon = self.column(rules.epsilon);
 
-		for i in 0..N {
-			if self.one_count[i] > self.values.len() as u32 / 2 {
-				gamma.bits[i] = 1;
-			} else {
-				epsilon.bits[i] = 1;
-			}
-		}
-
-		let oxygen = self.calculate_oxygen();
-		let co2 = self.calculate_co2();
+		self.values.sort_unstable();
+		let oxygen = self.filter(rules.oxygen);
+		let co2 = self.filter(rules.co2);
 
 		(gamma, epsilon, oxygen, co2)
 	}
 }
 
-fn main() {
-	let mut state = State::<12>::new();
+fn run<const N: usize>(first: &str, lines: impl Iterator<Item = String>, rules: &Rules) -> Result<(), (usize, ParseError)> {
+	let mut state = State::<N>::new();
 
-	for line in io::stdin().lock().lines() {
-		let bit_string = BitString::from_str(&line.unwrap()).unwrap();
-		println!("Input: {}, State: {:?}", bit_string.to_string(), (state.one_count, state.values.len()));
+	for (i, line) in Some(first.to_string()).into_iter().chain(lines).enumerate() {
+		let bit_string = BitString::from_str(&line).map_err(|e| (i + 1, e))?;
+		println!("Input: {}, State: {:?}", bit_string, (state.one_count, state.values.len()));
 		state.push(bit_string);
 	}
 
-	let (gamma, epsilon, oxygen, co2) = state.output();
-	println!("State: {:?}, Gamma: {}, Epsilon: {}, Oxygen: {}, CO2: {}", (state.one_count, state.values.len()), gamma.to_string(), epsilon.to_string(), oxygen.to_string(), co2.to_string());
+	let (gamma, epsilon, oxygen, co2) = state.output(rules);
+	println!("State: {:?}, Gamma: {}, Epsilon: {}, Oxygen: {}, CO2: {}", (state.one_count, state.values.len()), gamma.value, epsilon.value, oxygen.value, co2.value);
+
+	for (name, rating, criteria) in [("Gamma", &gamma, rules.gamma), ("Epsilon", &epsilon, rules.epsilon), ("Oxygen", &oxygen, rules.oxygen), ("CO2", &co2, rules.co2)] {
+		if !rating.ties.is_empty() {
+			println!("{}: tie at bits {:?}, kept {}", name, rating.ties, criteria.tie as u8);
+		}
+	}
+
+	// The product of two 64-bit ratings always fits in 128 bits
+	println!("Power consumption: {}", gamma.value.to_int() as u128 * epsilon.value.to_int() as u128);
+	println!("Life support rating: {}", oxygen.value.to_int() as u128 * co2.value.to_int() as u128);
+
+	Ok(())
+}
+
+// Monomorphizes `run` for every supported width, the width itself is only known at runtime
+macro_rules! dispatch {
+	($width:expr, $first:expr, $lines:expr, $rules:expr; $($n:literal),*) => {
+		match $width {
+			$($n => Some(run::<$n>($first, $lines, $rules)),)*
+			_ => None,
+		}
+	};
+}
+
+fn main() {
+	let mut rules = Rules::new();
+
+	for arg in env::args().skip(1) {
+		if rules.set(&arg).is_err() {
+			eprintln!("Invalid rule: {} (expected e.g. oxygen=most:1)", arg);
+			process::exit(1);
+		}
+	}
+
+	let stdin = io::stdin();
+	let mut input = stdin.lock().lines().map(|line| line.unwrap());
+
+	let first = match input.next() {
+		Some(line) => line,
+		None => return,
+	};
+	let width = first.chars().count();
+
+	let result = dispatch!(width, &first, input, &rules;
+		1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+		17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32,
+		33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48,
+		49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64
+	);
+
+	match result {
+		None => {
+			eprintln!("Unsupported report width: {}", width);
+			process::exit(1);
+		},
+		Some(Err((line, e))) => {
+			eprintln!("Line {}: {}", line, e);
+			process::exit(1);
+		},
+		Some(Ok(())) => {},
+	}
 }
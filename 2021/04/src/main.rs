@@ -1,4 +1,9 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io::{self, BufRead};
+use std::process;
 use ansi_term::Style;
 
 #[derive(Debug, Copy, Clone)]
@@ -7,136 +12,505 @@ struct Value {
 	marked: bool,
 }
 
-impl Value {
-	fn new() -> Self {
-		Self {
-			number: 0,
-			marked: false,
-		}
-	}
-}
-
-impl ToString for Value {
-	fn to_string(&self) -> String {
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		if self.marked {
-			return format!("{: <2}", Style::new().bold().paint(self.number.to_string()));
+			write!(f, "{: <2}", Style::new().bold().paint(self.number.to_string()))
 		} else {
-			return format!("{: <2}", self.number.to_string());
+			write!(f, "{: <2}", self.number)
 		}
 	}
 }
 
+type Cell = (usize, usize);
+type Mask = Vec<Vec<bool>>;
+
 #[derive(Debug)]
-struct Board<const N: usize> {
-	values: [[Value; N]; N],
-	fill_rows: [usize; N],
-	fill_columns: [usize; N],
-	pushed_rows: usize,
-	won: bool,
+enum Condition {
+	Rows,
+	Columns,
+	Diagonals,
+	Corners,
+	Blackout,
+	Masks(Vec<Mask>),
 }
 
-impl<const N: usize> Board<N> {
-	fn new() -> Self {
-		Self {
-			values: [[Value::new(); N]; N],
-			fill_rows: [0; N],
-			fill_columns: [0; N],
-			pushed_rows: 0,
-			won: false,
+impl Condition {
+	// Either a condition name or `masks=<path>`
+	fn parse(arg: &str) -> Result<Self, String> {
+		match arg {
+			"rows" => Ok(Self::Rows),
+			"columns" => Ok(Self::Columns),
+			"diagonals" => Ok(Self::Diagonals),
+			"corners" => Ok(Self::Corners),
+			"blackout" => Ok(Self::Blackout),
+			_ => match arg.strip_prefix("masks=") {
+				Some(path) => {
+					let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+					Ok(Self::Masks(Self::parse_masks(&content)?))
+				},
+				None => Err(format!("unknown win condition: {}", arg)),
+			},
 		}
 	}
 
-	fn push_row(&mut self, row: &str) {
-		let mut cols = row.split_whitespace();
+	// Masks are grids of `#` (required cell) and `.`, separated by blank lines
+	fn parse_masks(input: &str) -> Result<Vec<Mask>, String> {
+		let mut masks = vec![Mask::new()];
+
+		for line in input.lines().map(|line| line.trim()) {
+			if line.is_empty() {
+				if !masks.last().unwrap().is_empty() {
+					masks.push(Mask::new());
+				}
+				continue;
+			}
 
-		for i in 0..N {
-			self.values[self.pushed_rows][i].number = cols.next().unwrap().parse().unwrap();
+			let row = line.chars()
+				.map(|c| match c {
+					'#' => Ok(true),
+					'.' => Ok(false),
+					_ => Err(format!("invalid mask character: {:?}", c)),
+				})
+				.collect::<Result<Vec<bool>, String>>()?;
+			masks.last_mut().unwrap().push(row);
 		}
 
-		self.pushed_rows += 1;
+		masks.retain(|mask| !mask.is_empty());
+		Ok(masks)
 	}
 
-	fn mark(&mut self, number: u32) {
-		for i in 0..N {
-			for l in 0..N {
-				if self.values[i][l].number == number {
-					self.values[i][l].marked = true;
-					self.fill_rows[i] += 1;
-					self.fill_columns[l] += 1;
+	fn patterns(&self, n: usize) -> Result<Vec<Vec<Cell>>, String> {
+		let last = n - 1;
+
+		Ok(match self {
+			Self::Rows => (0..n).map(|i| (0..n).map(|l| (i, l)).collect()).collect(),
+			Self::Columns => (0..n).map(|l| (0..n).map(|i| (i, l)).collect()).collect(),
+			Self::Diagonals => vec![
+				(0..n).map(|i| (i, i)).collect(),
+				(0..n).map(|i| (i, last - i)).collect(),
+			],
+			Self::Corners => {
+				let mut corners = vec![(0, 0), (0, last), (last, 0), (last, last)];
+				corners.sort_unstable();
+				corners.dedup();
+				vec![corners]
+			},
+			Self::Blackout => vec![(0..n).flat_map(|i| (0..n).map(move |l| (i, l))).collect()],
+			Self::Masks(masks) => masks.iter()
+				.map(|mask| {
+					if mask.len() != n || mask.iter().any(|row| row.len() != n) {
+						return Err(format!("mask does not match the {}x{} board", n, n));
+					}
+
+					let cells: Vec<Cell> = (0..n)
+						.flat_map(|i| (0..n).map(move |l| (i, l)))
+						.filter(|&(i, l)| mask[i][l])
+						.collect();
+
+					if cells.is_empty() {
+						return Err("mask has no cells".to_string());
+					}
+
+					Ok(cells)
+				})
+				.collect::<Result<_, _>>()?,
+		})
+	}
+}
+
+// Winning cell sets shared by all boards of the same size
+#[derive(Debug)]
+struct Patterns {
+	n: usize,
+	cells: Vec<Vec<Cell>>,
+	by_cell: Vec<Vec<usize>>,
+}
+
+impl Patterns {
+	fn new(conditions: &[Condition], n: usize) -> Result<Self, String> {
+		let mut cells = vec![];
+		let mut by_cell = vec![vec![]; n * n];
+
+		for condition in conditions {
+			for pattern in condition.patterns(n)? {
+				for &(i, l) in pattern.iter() {
+					by_cell[i * n + l].push(cells.len());
 				}
+
+				cells.push(pattern);
 			}
 		}
+
+		Ok(Self {
+			n,
+			cells,
+			by_cell,
+		})
 	}
+}
+
+#[derive(Debug)]
+enum ParseError {
+	NoDraws,
+	NoBoards,
+	Number { line: usize, token: String },
+	Ragged { line: usize, expected: usize, found: usize },
+	NotSquare { board: usize, rows: usize, columns: usize },
+	Duplicate { board: usize, number: u32 },
+	SizeMismatch { board: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::NoDraws => write!(f, "missing drawn numbers"),
+			Self::NoBoards => write!(f, "no boards"),
+			Self::Number { line, token } => write!(f, "line {}: invalid number {:?}", line, token),
+			Self::Ragged { line, expected, found } => write!(f, "line {}: expected {} numbers, found {}", line, expected, found),
+			Self::NotSquare { board, rows, columns } => write!(f, "board {}: {} rows of {} numbers, expected a square board", board, rows, columns),
+			Self::Duplicate { board, number } => write!(f, "board {}: number {} appears more than once", board, number),
+			Self::SizeMismatch { board, expected, found } => write!(f, "board {}: size {} differs from the first board size {}", board, found, expected),
+		}
+	}
+}
 
-	fn check_win(&mut self) -> bool {
-		for i in 0..N {
-			if self.fill_rows[i] == N || self.fill_columns[i] == N {
-				self.won = true;
-				return true;
+fn parse_numbers<'a>(line: usize, tokens: impl Iterator<Item = &'a str>) -> Result<Vec<u32>, ParseError> {
+	tokens
+		.map(|token| token.parse().map_err(|_| ParseError::Number { line, token: token.to_string() }))
+		.collect()
+}
+
+// `lines` are the non-blank (line number, line) pairs of a single board
+fn parse_board(board: usize, lines: &[(usize, String)]) -> Result<Vec<Vec<u32>>, ParseError> {
+	let mut rows = Vec::<Vec<u32>>::with_capacity(lines.len());
+
+	for (line, content) in lines.iter() {
+		let row = parse_numbers(*line, content.split_whitespace())?;
+
+		if let Some(first) = rows.first() {
+			if row.len() != first.len() {
+				return Err(ParseError::Ragged { line: *line, expected: first.len(), found: row.len() });
 			}
 		}
 
-		false
+		rows.push(row);
+	}
+
+	if rows.len() != rows[0].len() {
+		return Err(ParseError::NotSquare { board, rows: rows.len(), columns: rows[0].len() });
+	}
+
+	let mut seen = HashSet::<u32>::new();
+	for &number in rows.iter().flatten() {
+		if !seen.insert(number) {
+			return Err(ParseError::Duplicate { board, number });
+		}
 	}
+
+	Ok(rows)
 }
 
-impl<const N: usize> ToString for Board<N> {
-	fn to_string(&self) -> String {
-		let mut out: String = String::new();
+// Unmarked sum times the winning number, which may not fit even in 64 bits for large boards
+#[derive(Debug, Copy, Clone)]
+enum Score {
+	Value(u64),
+	Overflow,
+}
 
-		for i in 0..N {
-			for l in 0..N {
-				out += &self.values[i][l].to_string();
-				out += " ";
+impl fmt::Display for Score {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Value(score) => write!(f, "{}", score),
+			Self::Overflow => write!(f, "overflow"),
+		}
+	}
+}
+
+#[derive(Debug)]
+struct Board {
+	values: Vec<Vec<Value>>,
+	index: HashMap<u32, Cell>,
+	fill: Vec<usize>,
+	unmarked: u64,
+	score: Option<Score>,
+}
+
+impl Board {
+	// `rows` must come from `parse_board` and match the size of `patterns`
+	fn new(rows: &[Vec<u32>], patterns: &Patterns) -> Self {
+		let mut index = HashMap::with_capacity(patterns.n * patterns.n);
+
+		let values = rows.iter()
+			.enumerate()
+			.map(|(i, row)| {
+				row.iter()
+					.enumerate()
+					.map(|(l, &number)| {
+						index.insert(number, (i, l));
+						Value { number, marked: false }
+					})
+					.collect()
+			})
+			.collect();
+
+		Self {
+			values,
+			index,
+			fill: vec![0; patterns.cells.len()],
+			unmarked: rows.iter().flatten().map(|&number| number as u64).sum(),
+			score: None,
+		}
+	}
+
+	fn numbers<'a>(&'a self, cells: &'a [Cell]) -> impl Iterator<Item = u32> + 'a {
+		cells.iter().map(move |&(i, l)| self.values[i][l].number)
+	}
+
+	// Returns true if the board won with this cell, only the patterns containing it can complete,
+	// the score is fixed at the moment of winning
+	fn mark(&mut self, (i, l): Cell, patterns: &Patterns) -> bool {
+		let value = &mut self.values[i][l];
+		if value.marked {
+			return false;
+		}
+
+		value.marked = true;
+		self.unmarked -= value.number as u64;
+		let mut complete = false;
+		for &pattern in patterns.by_cell[i * patterns.n + l].iter() {
+			self.fill[pattern] += 1;
+			complete |= self.fill[pattern] == patterns.cells[pattern].len();
+		}
+
+		if self.score.is_none() && complete {
+			self.score = Some(self.unmarked.checked_mul(value.number as u64).map_or(Score::Overflow, Score::Value));
+			return true;
+		}
+
+		false
+	}
+}
+
+impl fmt::Display for Board {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (i, row) in self.values.iter().enumerate() {
+			if i != 0 {
+				writeln!(f)?;
 			}
 
-			out.pop();
-			out += "\n";
+			let row: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+			write!(f, "{}", row.join(" "))?;
 		}
 
-		out.pop();
-		out
+		Ok(())
 	}
 }
 
+#[derive(Debug)]
+struct Reorder {
+	draws: Vec<u32>,
+	wins_after: usize,
+	moved: usize,
+}
+
+#[derive(Debug)]
+struct Analysis {
+	prefix: Option<usize>,
+	reorder: Option<Reorder>,
+}
+
+// Finds the draws making `target` the sole first winner: the shortest prefix of the original draws,
+// and the shortest reordering, which is the smallest pattern of `target` whose numbers are all drawn
+// and do not complete any pattern of another board, followed by the remaining draws in order
+fn analyze(target: usize, boards: &[Board], patterns: &Patterns, numbers: &[u32], timeline: &[Option<usize>]) -> Analysis {
+	let prefix = timeline[target].filter(|&draw| {
+		timeline.iter()
+			.enumerate()
+			.all(|(i, other)| i == target || other.is_none_or(|other| other > draw))
+	});
+
+	let mut first_draw = HashMap::<u32, usize>::new();
+	for (draw, &number) in numbers.iter().enumerate() {
+		first_draw.entry(number).or_insert(draw);
+	}
+
+	let best = patterns.cells.iter()
+		.filter_map(|cells| {
+			let drawn = boards[target].numbers(cells)
+				.map(|number| first_draw.get(&number).map(|&draw| (draw, number)))
+				.collect::<Option<Vec<(usize, u32)>>>()?;
+			let set: HashSet<u32> = drawn.iter().map(|&(_, number)| number).collect();
+
+			let blocked = boards.iter()
+				.enumerate()
+				.filter(|&(i, _)| i != target)
+				.any(|(_, board)| {
+					patterns.cells.iter().any(|cells| board.numbers(cells).all(|number| set.contains(&number)))
+				});
+
+			if blocked { None } else { Some(drawn) }
+		})
+		.min_by_key(|drawn| (drawn.len(), drawn.iter().map(|&(draw, _)| draw).max()));
+
+	let reorder = best.map(|mut drawn| {
+		drawn.sort_unstable();
+		let used: HashSet<usize> = drawn.iter().map(|&(draw, _)| draw).collect();
+		let moved = drawn.iter()
+			.enumerate()
+			.filter(|&(i, &(draw, _))| i != draw)
+			.count();
+
+		let draws = drawn.iter()
+			.map(|&(_, number)| number)
+			.chain(numbers.iter()
+				.enumerate()
+				.filter(|(draw, _)| !used.contains(draw))
+				.map(|(_, &number)| number))
+			.collect();
+
+		Reorder {
+			draws,
+			wins_after: drawn.len(),
+			moved,
+		}
+	});
+
+	Analysis {
+		prefix,
+		reorder,
+	}
+}
+
+fn exit_with(e: impl fmt::Display) -> ! {
+	eprintln!("{}", e);
+	process::exit(1);
+}
+
 fn main() {
-	let mut boards = Vec::<Board<5>>::new();
-	let mut numbers = Vec::<u32>::new();
+	let mut conditions = Vec::<Condition>::new();
+	let mut target = None::<usize>;
+
+	for arg in env::args().skip(1) {
+		match arg.strip_prefix("target=") {
+			Some(board) => target = Some(board.parse().unwrap_or_else(|_| exit_with(format!("invalid target board: {}", board)))),
+			None => conditions.push(Condition::parse(&arg).unwrap_or_else(|e| exit_with(e))),
+		}
+	}
+	if conditions.is_empty() {
+		conditions = vec![Condition::Rows, Condition::Columns];
+	}
+
 	let stdin = io::stdin();
-	let mut input = stdin.lock().lines();
+	let mut input = stdin.lock().lines()
+		.map(|line| line.unwrap())
+		.enumerate()
+		.map(|(i, line)| (i + 1, line))
+		.filter(|(_, line)| !line.trim().is_empty());
 
 	// Drawn numbers
-	for number in input.next().unwrap().unwrap().split(",") {
-		numbers.push(number.parse().unwrap());
-	}
+	let (line, draws) = input.next().unwrap_or_else(|| exit_with(ParseError::NoDraws));
+	let numbers = parse_numbers(line, draws.split(',').map(|token| token.trim()))
+		.unwrap_or_else(|e| exit_with(e));
 
-	// Boards
-	for line in input {
-		let line = line.unwrap();
+	// Boards, separated by any number of blank lines, a board is also complete once it has as many
+	// rows as its first row has numbers, so boards without a blank line between them are split too
+	let mut grids = Vec::<Vec<Vec<u32>>>::new();
+	let mut lines = Vec::<(usize, String)>::new();
 
-		if line == "" {
-			if boards.len() != 0 {
-				println!("\nLoaded board:\n{}", boards.last().unwrap().to_string());
+	for (line, content) in input {
+		if let Some(&(last, _)) = lines.last() {
+			let size = lines[0].1.split_whitespace().count();
+			if line != last + 1 || lines.len() == size {
+				grids.push(parse_board(grids.len(), &lines).unwrap_or_else(|e| exit_with(e)));
+				lines.clear();
 			}
+		}
 
-			boards.push(Board::new());
-		} else {
-			boards.last_mut().unwrap().push_row(&line);
+		lines.push((line, content));
+	}
+	if !lines.is_empty() {
+		grids.push(parse_board(grids.len(), &lines).unwrap_or_else(|e| exit_with(e)));
+	}
+
+	let n = grids.first().unwrap_or_else(|| exit_with(ParseError::NoBoards)).len();
+	for (board, grid) in grids.iter().enumerate() {
+		if grid.len() != n {
+			exit_with(ParseError::SizeMismatch { board, expected: n, found: grid.len() });
 		}
 	}
-	println!("\nLoaded board:\n{}", boards.last().unwrap().to_string());
 
-	// Mark numbers
+	let patterns = Patterns::new(&conditions, n).unwrap_or_else(|e| exit_with(e));
+	let mut boards: Vec<Board> = grids.iter().map(|grid| Board::new(grid, &patterns)).collect();
+
+	for board in boards.iter() {
+		println!("\nLoaded board:\n{}", board);
+	}
+
+	// Cells holding each number across all boards, in board order
+	let mut index = HashMap::<u32, Vec<(usize, Cell)>>::new();
+	for (i, board) in boards.iter().enumerate() {
+		for (&number, &cell) in board.index.iter() {
+			index.entry(number).or_default().push((i, cell));
+		}
+	}
+	for cells in index.values_mut() {
+		cells.sort_unstable();
+	}
+
+	// Mark numbers, winners are (draw index, board index) in order of winning,
+	// the timeline holds the winning draw index of every board
+	let mut winners = Vec::<(usize, usize)>::new();
+	let mut timeline = vec![None::<usize>; boards.len()];
+
 	println!();
-	for number in numbers {
+	for (draw, &number) in numbers.iter().enumerate() {
 		println!("Marking number: {}", number);
 
-		for board in boards.iter_mut() {
-			board.mark(number);
-
-			if !board.won && board.check_win() {
-				println!("\nWinning board:\n{}", board.to_string());
+		for &(i, cell) in index.get(&number).into_iter().flatten() {
+			if boards[i].mark(cell, &patterns) {
+				println!("\nWinning board (score {}):\n{}", boards[i].score.unwrap(), boards[i]);
+				winners.push((draw, i));
+				timeline[i] = Some(draw);
 			}
 		}
 	}
+
+	println!();
+	match (winners.first(), winners.last()) {
+		(Some(&(first_draw, first)), Some(&(last_draw, last))) => {
+			println!("First winner: board {} at draw {}, score {}", first, first_draw, boards[first].score.unwrap());
+			println!("Last winner: board {} at draw {}, score {}", last, last_draw, boards[last].score.unwrap());
+		},
+		_ => println!("No board won"),
+	}
+
+	println!("\nTimeline:");
+	for (i, draw) in timeline.iter().enumerate() {
+		match draw {
+			Some(draw) => println!("Board {}: wins at draw {}", i, draw),
+			None => println!("Board {}: never wins", i),
+		}
+	}
+
+	if let Some(target) = target {
+		if target >= boards.len() {
+			exit_with(format!("target board {} does not exist", target));
+		}
+
+		let analysis = analyze(target, &boards, &patterns, &numbers, &timeline);
+
+		println!("\nTarget board {}:", target);
+		match analysis.prefix {
+			Some(draw) => println!("Wins first with the original draws, prefix of {} draws", draw + 1),
+			None => println!("Does not win first with the original draws"),
+		}
+		match analysis.reorder {
+			Some(reorder) => {
+				let draws: Vec<String> = reorder.draws.iter().map(|number| number.to_string()).collect();
+				println!("Wins first after {} draws when moving {} draws forward: {}", reorder.wins_after, reorder.moved, draws.join(","));
+			},
+			None => println!("Cannot win first with any reordering of the draws"),
+		}
+	}
 }
@@ -1,12 +1,38 @@
+use std::env;
+use std::fmt;
 use std::io::{self, BufRead};
+use std::process;
 use std::str::FromStr;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 type Pos = (u32, u32);
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+	Horizontal,
+	Vertical,
+	Diagonal,
+	Other,
+}
+
 #[derive(Debug)]
+enum ParseError {
+	Format(String),
+	Slope(Line),
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Format(input) => write!(f, "invalid line {:?}, expected x1,y1 -> x2,y2", input),
+			Self::Slope(line) => write!(f, "{} is neither axis-aligned nor 45° diagonal", line),
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
 struct Line {
 	start: Pos,
 	end: Pos,
@@ -15,88 +41,474 @@ struct Line {
 impl Line {
 	fn new(start: Pos, end: Pos) -> Self {
 		Self {
-			start: start,
-			end: end,
+			start,
+			end,
 		}
 	}
 
-	fn fields(&self) -> Vec<Pos> {
-		let mut out = Vec::<Pos>::new();
-		let mut pos = self.start.clone();
+	fn kind(&self) -> Kind {
+		let dx = self.start.0.abs_diff(self.end.0);
+		let dy = self.start.1.abs_diff(self.end.1);
 
-		while pos != self.end {
-			out.push(pos);
-			pos.0 = (pos.0 as i64 + (1 * (self.end.0 as i64 - pos.0 as i64).signum())) as u32;
-			pos.1 = (pos.1 as i64 + (1 * (self.end.1 as i64 - pos.1 as i64).signum())) as u32;
+		if dy == 0 {
+			Kind::Horizontal
+		} else if dx == 0 {
+			Kind::Vertical
+		} else if dx == dy {
+			Kind::Diagonal
+		} else {
+			Kind::Other
 		}
+	}
+
+	fn bounds<'a>(lines: impl Iterator<Item = &'a Line>) -> Option<(Pos, Pos)> {
+		lines
+			.flat_map(|line| [line.start, line.end])
+			.fold(None, |bounds, pos| match bounds {
+				None => Some((pos, pos)),
+				Some((min, max)) => Some(((min.0.min(pos.0), min.1.min(pos.1)), (max.0.max(pos.0), max.1.max(pos.1)))),
+			})
+	}
+
+	// Number of cells rasterized for the line, one per step along the longer axis
+	fn len(&self) -> u64 {
+		self.start.0.abs_diff(self.end.0).max(self.start.1.abs_diff(self.end.1)) as u64 + 1
+	}
 
-		out.push(pos);
-		out
+	fn fields(&self) -> Fields {
+		Fields::new(self)
 	}
 }
 
 impl FromStr for Line {
-	type Err = ();
+	type Err = ParseError;
 
 	fn from_str(input: &str) -> Result<Self, Self::Err> {
 		lazy_static! {
 			static ref REGEX: Regex = Regex::new("^(\\d+),(\\d+) -> (\\d+),(\\d+)$").unwrap();
 		}
 
-		let capture = REGEX.captures(input).unwrap();
+		let error = || ParseError::Format(input.to_string());
+		let capture = REGEX.captures(input).ok_or_else(error)?;
+		let coord = |i| capture.get(i).unwrap().as_str().parse().map_err(|_| error());
+
+		Ok(Self::new((coord(1)?, coord(2)?), (coord(3)?, coord(4)?)))
+	}
+}
+
+impl fmt::Display for Line {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{},{} -> {},{}", self.start.0, self.start.1, self.end.0, self.end.1)
+	}
+}
+
+// Bresenham rasterization, for axis-aligned and 45° lines it visits exactly the cells on the line.
+// Lines are always walked from their lower endpoint, so x never decreases and both directions
+// of the same line cover the same cells
+#[derive(Debug)]
+struct Fields {
+	pos: (i64, i64),
+	end: (i64, i64),
+	step: (i64, i64),
+	delta: (i64, i64),
+	error: i64,
+	done: bool,
+}
+
+impl Fields {
+	fn new(line: &Line) -> Self {
+		let (start, end) = if line.start <= line.end { (line.start, line.end) } else { (line.end, line.start) };
+		let pos = (start.0 as i64, start.1 as i64);
+		let end = (end.0 as i64, end.1 as i64);
+		let delta = ((end.0 - pos.0).abs(), -(end.1 - pos.1).abs());
+
+		Self {
+			pos,
+			end,
+			step: ((end.0 - pos.0).signum(), (end.1 - pos.1).signum()),
+			delta,
+			error: delta.0 + delta.1,
+			done: false,
+		}
+	}
+}
+
+impl Iterator for Fields {
+	type Item = Pos;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return None;
+		}
+
+		let out = (self.pos.0 as u32, self.pos.1 as u32);
+
+		if self.pos == self.end {
+			self.done = true;
+			return Some(out);
+		}
+
+		let error = 2 * self.error;
+		if error >= self.delta.1 {
+			self.error += self.delta.1;
+			self.pos.0 += self.step.0;
+		}
+		if error <= self.delta.0 {
+			self.error += self.delta.0;
+			self.pos.1 += self.step.1;
+		}
+
+		Some(out)
+	}
+}
 
-		let start_x = capture.get(1).unwrap().as_str().parse().unwrap();
-		let start_y = capture.get(2).unwrap().as_str().parse().unwrap();
-		let end_x = capture.get(3).unwrap().as_str().parse().unwrap();
-		let end_y = capture.get(4).unwrap().as_str().parse().unwrap();
+#[derive(Debug, Copy, Clone)]
+enum Filter {
+	AxisAligned,
+	Diagonal,
+	Any,
+}
 
-		Ok(Self::new((start_x, start_y), (end_x, end_y)))
+impl Filter {
+	fn accepts(self, line: &Line) -> bool {
+		match (self, line.kind()) {
+			(Self::AxisAligned, Kind::Horizontal | Kind::Vertical) => true,
+			(Self::Diagonal, kind) => kind != Kind::Other,
+			(Self::Any, _) => true,
+			_ => false,
+		}
 	}
 }
 
+// Bounding boxes up to this many cells are counted in a flat array instead of a hash map
+const DENSE_LIMIT: u64 = 4_000_000;
+
+#[derive(Debug)]
+enum Backend {
+	Dense(Vec<u16>),
+	Sparse(HashMap<Pos, u32>),
+}
+
 #[derive(Debug)]
 struct State {
-	fields: HashMap<Pos, u32>,
+	filter: Filter,
+	min: Pos,
+	max: Pos,
+	fields: Backend,
 }
 
 impl State {
-	fn new() -> Self {
+	// Number of cells of the bounding box if it is small enough for the dense backend
+	fn dense_area(min: Pos, max: Pos) -> Option<usize> {
+		((max.0 - min.0) as u64 + 1)
+			.checked_mul((max.1 - min.1) as u64 + 1)
+			.filter(|&area| area <= DENSE_LIMIT)
+			.map(|area| area as usize)
+	}
+
+	// `min` and `max` must enclose every line pushed later
+	fn new(filter: Filter, min: Pos, max: Pos) -> Self {
+		let fields = if let Some(area) = Self::dense_area(min, max) {
+			Backend::Dense(vec![0; area])
+		} else {
+			Backend::Sparse(HashMap::new())
+		};
+
 		Self {
-			fields: HashMap::new(),
+			filter,
+			min,
+			max,
+			fields,
 		}
 	}
 
+	// Only meaningful for the dense backend, the bounding box of the sparse one may not fit in `usize`
+	fn index(min: Pos, max: Pos, pos: Pos) -> usize {
+		(pos.1 - min.1) as usize * (max.0 - min.0 + 1) as usize + (pos.0 - min.0) as usize
+	}
+
 	fn push(&mut self, line: &Line) {
+		if !self.filter.accepts(line) {
+			return;
+		}
+
 		for field in line.fields() {
-			match self.fields.get_mut(&field) {
-				Some(field) => {
-					*field += 1;
+			match &mut self.fields {
+				Backend::Dense(fields) => {
+					let index = Self::index(self.min, self.max, field);
+					fields[index] = fields[index].saturating_add(1);
 				},
-				None => {
-					self.fields.insert(field, 1);
+				Backend::Sparse(fields) => {
+					*fields.entry(field).or_insert(0) += 1;
 				},
 			}
 		}
 	}
 
-	fn multi_fields(&self, treshold: u32) -> HashMap<Pos, u32> {
-		self.fields.clone()
-			.into_iter()
-			.filter(|val| val.1 >= treshold)
-			.collect()
+	fn get(&self, pos: Pos) -> u32 {
+		match &self.fields {
+			Backend::Dense(fields) => fields[Self::index(self.min, self.max, pos)] as u32,
+			Backend::Sparse(fields) => fields.get(&pos).copied().unwrap_or(0),
+		}
+	}
+
+	fn multi_fields(&self, treshold: u32) -> usize {
+		match &self.fields {
+			Backend::Dense(fields) => fields.iter().filter(|&&val| val as u32 >= treshold).count(),
+			Backend::Sparse(fields) => fields.values().filter(|&&val| val >= treshold).count(),
+		}
+	}
+
+	// One character per cell in the puzzle notation, `.` for no line and `+` for more than 9
+	fn heatmap(&self) -> String {
+		(self.min.1..=self.max.1)
+			.map(|y| {
+				(self.min.0..=self.max.0)
+					.map(|x| match self.get((x, y)) {
+						0 => '.',
+						count @ 1..=9 => char::from_digit(count, 10).unwrap(),
+						_ => '+',
+					})
+					.collect::<String>()
+			})
+			.collect::<Vec<String>>()
+			.join("\n")
+	}
+}
+
+// Families of lines counted analytically, every line of a family lies on a carrier identified by a key,
+// and covers an interval of positions along it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Family {
+	Horizontal,
+	Vertical,
+	Rising,
+	Falling,
+}
+
+const FAMILIES: [Family; 4] = [Family::Horizontal, Family::Vertical, Family::Rising, Family::Falling];
+
+impl Family {
+	fn of(line: &Line) -> Option<Self> {
+		match line.kind() {
+			Kind::Horizontal => Some(Self::Horizontal),
+			Kind::Vertical => Some(Self::Vertical),
+			Kind::Diagonal if (line.start.0 < line.end.0) == (line.start.1 < line.end.1) => Some(Self::Rising),
+			Kind::Diagonal => Some(Self::Falling),
+			Kind::Other => None,
+		}
+	}
+
+	fn key(self, (x, y): (i64, i64)) -> i64 {
+		match self {
+			Self::Horizontal => y,
+			Self::Vertical => x,
+			Self::Rising => x - y,
+			Self::Falling => x + y,
+		}
+	}
+
+	fn position(self, (x, y): (i64, i64)) -> i64 {
+		match self {
+			Self::Vertical => y,
+			_ => x,
+		}
+	}
+
+	// The lattice point where the carriers of two different families cross, if there is one
+	fn cross(self, key: i64, other: Self, other_key: i64) -> Option<(i64, i64)> {
+		match (self, other) {
+			(Self::Horizontal, Self::Vertical) => Some((other_key, key)),
+			(Self::Horizontal, Self::Rising) => Some((key + other_key, key)),
+			(Self::Horizontal, Self::Falling) => Some((other_key - key, key)),
+			(Self::Vertical, Self::Rising) => Some((key, key - other_key)),
+			(Self::Vertical, Self::Falling) => Some((key, other_key - key)),
+			(Self::Rising, Self::Falling) if (key + other_key) % 2 == 0 => Some(((key + other_key) / 2, (other_key - key) / 2)),
+			(Self::Rising, Self::Falling) => None,
+			_ => other.cross(other_key, self, key),
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Segment {
+	family: Family,
+	key: i64,
+	from: i64,
+	to: i64,
+}
+
+impl Segment {
+	fn new(family: Family, line: &Line) -> Self {
+		let start = (line.start.0 as i64, line.start.1 as i64);
+		let end = (line.end.0 as i64, line.end.1 as i64);
+		let (a, b) = (family.position(start), family.position(end));
+
+		Self {
+			family,
+			key: family.key(start),
+			from: a.min(b),
+			to: a.max(b),
+		}
+	}
+
+	fn contains(&self, pos: (i64, i64)) -> bool {
+		self.family.key(pos) == self.key && (self.from..=self.to).contains(&self.family.position(pos))
+	}
+}
+
+// Runs of constant overlap depth along each carrier, as (from, to exclusive, depth)
+type Runs = HashMap<(Family, i64), Vec<(i64, i64, u32)>>;
+
+fn depth(runs: &Runs, family: Family, pos: (i64, i64)) -> u32 {
+	let runs = match runs.get(&(family, family.key(pos))) {
+		Some(runs) => runs,
+		None => return 0,
+	};
+
+	let position = family.position(pos);
+	match runs.partition_point(|&(from, _, _)| from <= position).checked_sub(1) {
+		Some(i) if position < runs[i].1 => runs[i].2,
+		_ => 0,
+	}
+}
+
+// Counts the cells covered by at least `threshold` lines without visiting covered cells: overlaps of lines on
+// the same carrier are merged as intervals, and only the points where carriers of different families cross
+// are looked at one by one. Lines of other slopes are still rasterized, their cells are treated like crossings
+fn sweep(lines: &[Line], filter: Filter, threshold: u32) -> usize {
+	let mut segments = Vec::<Segment>::new();
+	let mut others = HashMap::<(i64, i64), u32>::new();
+
+	for line in lines.iter().filter(|line| filter.accepts(line)) {
+		match Family::of(line) {
+			Some(family) => segments.push(Segment::new(family, line)),
+			None => for (x, y) in line.fields() {
+				*others.entry((x as i64, y as i64)).or_insert(0) += 1;
+			},
+		}
+	}
+
+	// Interval overlaps of every carrier as +1/-1 events
+	let mut events = HashMap::<(Family, i64), Vec<(i64, i32)>>::new();
+	for segment in segments.iter() {
+		let events = events.entry((segment.family, segment.key)).or_default();
+		events.push((segment.from, 1));
+		events.push((segment.to + 1, -1));
+	}
+
+	let mut runs = Runs::new();
+	let mut count = 0;
+	for (carrier, mut events) in events {
+		events.sort_unstable();
+
+		let mut carrier_runs = vec![];
+		let mut depth = 0;
+		let mut from = 0;
+		for &(position, change) in events.iter() {
+			if depth > 0 && position > from {
+				carrier_runs.push((from, position, depth as u32));
+				if depth >= threshold as i32 {
+					count += (position - from) as usize;
+				}
+			}
+
+			depth += change;
+			from = position;
+		}
+
+		runs.insert(carrier, carrier_runs);
+	}
+
+	// Crossings of different families pairwise, the cells of other lines are added to them
+	let mut special: HashSet<(i64, i64)> = others.keys().copied().collect();
+	for (i, a) in segments.iter().enumerate() {
+		for b in segments[i + 1..].iter().filter(|b| b.family != a.family) {
+			if let Some(pos) = a.family.cross(a.key, b.family, b.key).filter(|&pos| a.contains(pos) && b.contains(pos)) {
+				special.insert(pos);
+			}
+		}
 	}
+
+	// Each special point was counted once for every family reaching the threshold alone,
+	// replace that with a single count of its total depth
+	for pos in special {
+		let depths = FAMILIES.map(|family| depth(&runs, family, pos));
+		let alone = depths.iter().filter(|&&depth| depth >= threshold).count();
+		let total = depths.iter().sum::<u32>() + others.get(&pos).copied().unwrap_or(0);
+
+		count -= alone;
+		if total >= threshold {
+			count += 1;
+		}
+	}
+
+	count
 }
 
 fn main() {
-	let mut state = State::new();
+	// With `any`, lines of arbitrary slope are rasterized instead of rejected,
+	// with `sweep` the overlaps are counted from the line segments instead of cell by cell,
+	// with `heatmap` the overlaps of the last filter are printed
+	let args: Vec<String> = env::args().skip(1).collect();
+	let any = args.iter().any(|arg| arg == "any");
+	let use_sweep = args.iter().any(|arg| arg == "sweep");
+	let heatmap = args.iter().any(|arg| arg == "heatmap");
 
-	for line in io::stdin().lock().lines() {
-		let line = Line::from_str(&line.unwrap()).unwrap();
-		println!("Line: {:?}, Fields: {:?}", line, line.fields().len());
-		state.push(&line);
+	let mut filters = vec![Filter::AxisAligned, Filter::Diagonal];
+	if any {
+		filters.push(Filter::Any);
 	}
 
-	let multi_fields = state.multi_fields(2);
+	let mut lines = Vec::<Line>::new();
+
+	for (i, line) in io::stdin().lock().lines().enumerate() {
+		let line = Line::from_str(&line.unwrap())
+			.and_then(|line| match line.kind() {
+				Kind::Other if !any => Err(ParseError::Slope(line)),
+				_ => Ok(line),
+			})
+			.unwrap_or_else(|e| {
+				eprintln!("Line {}: {}", i + 1, e);
+				process::exit(1);
+			});
+		println!("Line: {:?}, Fields: {:?}", line, line.len());
+		lines.push(line);
+	}
+
+	let state = |filter: Filter| {
+		let (min, max) = Line::bounds(lines.iter().filter(|line| filter.accepts(line)))?;
+		let mut state = State::new(filter, min, max);
+		for line in lines.iter() {
+			state.push(line);
+		}
 
-	println!("{:?}", multi_fields.len());
+		Some(state)
+	};
+
+	for &filter in filters.iter() {
+		let count = if use_sweep {
+			sweep(&lines, filter, 2)
+		} else {
+			state(filter).map_or(0, |state| state.multi_fields(2))
+		};
+
+		println!("{:?}: {}", filter, count);
+	}
+
+	// The heatmap has one character per cell, so it is only drawn for bounding boxes fitting the dense backend
+	if heatmap {
+		let filter = *filters.last().unwrap();
+
+		match Line::bounds(lines.iter().filter(|line| filter.accepts(line))) {
+			Some((min, max)) if State::dense_area(min, max).is_none() => {
+				println!("--- Heatmap: {:?} from {:?} to {:?} exceeds {} cells ---", filter, min, max, DENSE_LIMIT);
+			},
+			Some(_) => {
+				let state = state(filter).unwrap();
+				println!("--- Heatmap: {:?} from {:?} to {:?} ---\n{}", state.filter, state.min, state.max, state.heatmap());
+			},
+			None => {},
+		}
+	}
 }
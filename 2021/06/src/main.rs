@@ -1,57 +1,244 @@
-use std::io::{self, BufRead};
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::process;
+use std::str::FromStr;
 use ansi_term::Style;
+use num_bigint::BigUint;
 
-#[derive(Debug)]
-struct State<const M: usize, const N: usize>{
-	queue: [u64; M],
-	new: [u64; M],
-	next: usize,
+// Exact populations grow by ~1.09x (about 0.125 bits) per day, so the limit already means numbers
+// of ~150 KB, beyond this a modulus is required
+const EXACT_LIMIT: u64 = 10_000_000;
+
+// Exact histograms have a row of such numbers for every day, so their size grows with the square
+// of the days, under 1 MB at this limit, beyond this the export requires a modulus
+const EXACT_HISTOGRAM_LIMIT: u64 = 2_000;
+
+// Fish in the reproduction cycle are kept in a ring indexed by the day their timer hits zero,
+// newborns wait `delay` days in a separate ring before joining the cycle
+#[derive(Debug, Clone)]
+struct State {
+	cycle: usize,
+	delay: usize,
+	queue: Vec<u64>,
+	new: Vec<u64>,
+	day: usize,
 }
 
-impl<const M: usize, const N: usize> State<M, N> {
-	fn new() -> Self {
+impl State {
+	fn new(cycle: usize, delay: usize) -> Self {
+		assert!(cycle > 0);
+
 		Self {
-			queue: [0; M],
-			new: [0; M],
-			next: 0,
+			cycle,
+			delay,
+			queue: vec![0; cycle],
+			new: vec![0; delay + 1],
+			day: 0,
 		}
 	}
 
-	fn push(&mut self, timer: u64) {
-		assert!((timer as usize) < M + N);
+	fn push(&mut self, timer: usize) -> Result<(), String> {
+		if timer >= self.cycle + self.delay {
+			return Err(format!("timer {} exceeds the maximum of {}", timer, self.cycle + self.delay - 1));
+		}
 
-		if (timer as usize) < M {
-			self.queue[(self.next + timer as usize) % M] += 1;
+		if timer < self.cycle {
+			self.queue[(self.day + timer) % self.cycle] += 1;
 		} else {
-			self.new[(self.next + timer as usize - M) % M] += 1;
+			let index = (self.day + timer - self.cycle) % self.new.len();
+			self.new[index] += 1;
 		}
+
+		Ok(())
 	}
 
-	fn day(&mut self) {
-		let new_count = self.new[self.next];
-		self.new[(self.next + N) % M] += self.queue[self.next];
-		self.queue[self.next] += new_count;
-		self.new[self.next] = 0;
-		self.next = (self.next + 1) % M;
+	// Returns None if the total would overflow, the state is left unchanged in that case
+	fn day(&mut self) -> Option<()> {
+		let current = self.day % self.cycle;
+		let matured = self.day % self.new.len();
+		let born = (self.day + self.delay) % self.new.len();
+		let spawning = self.queue[current];
+
+		// Every count fits as long as the total does
+		self.total().checked_add(spawning)?;
+
+		// Without a delay the newborns join the cycle right away, otherwise they take the slot
+		// matured yesterday
+		if self.delay == 0 {
+			self.queue[current] += spawning;
+		} else {
+			self.queue[current] += self.new[matured];
+			self.new[matured] = 0;
+			self.new[born] = spawning;
+		}
+
+		self.day += 1;
+		Some(())
 	}
 
 	fn total(&self) -> u64 {
 		self.queue.iter().sum::<u64>() + self.new.iter().sum::<u64>()
 	}
+
+	// Fish counts indexed by timer value
+	fn timers(&self) -> Vec<u64> {
+		(0..self.cycle + self.delay)
+			.map(|timer| {
+				if timer < self.cycle {
+					self.queue[(self.day + timer) % self.cycle]
+				} else {
+					self.new[(self.day + timer - self.cycle) % self.new.len()]
+				}
+			})
+			.collect()
+	}
+
+	// Population after `days` more days, as the timer vector times the `days`-th power of the transition matrix
+	fn population(&self, days: u64, modulus: Option<&BigUint>) -> BigUint {
+		let timers: Vec<BigUint> = self.timers().into_iter().map(BigUint::from).collect();
+		let total = Matrix::transition(self.cycle, self.delay)
+			.pow(days, modulus)
+			.apply(&timers, modulus)
+			.into_iter()
+			.sum::<BigUint>();
+
+		match modulus {
+			Some(modulus) => total % modulus,
+			None => total,
+		}
+	}
 }
 
-impl<const M: usize, const N: usize> ToString for State<M, N> {
-	fn to_string(&self) -> String {
-		format!(
-			"{} = {}",
-			(0..M)
+// Square matrix, the modulus is applied after every multiplication when set
+#[derive(Debug, Clone)]
+struct Matrix(Vec<Vec<BigUint>>);
+
+impl Matrix {
+	fn identity(size: usize) -> Self {
+		Self(
+			(0..size)
+				.map(|i| (0..size).map(|l| BigUint::from((i == l) as u8)).collect())
+				.collect()
+		)
+	}
+
+	// Column `t` holds what a fish with timer `t` becomes after one day, for a reproduction
+	// cycle of `m` days and `n` extra days before newborns join the cycle
+	fn transition(m: usize, n: usize) -> Self {
+		let mut out = Self(vec![vec![BigUint::from(0u8); m + n]; m + n]);
+
+		for timer in 1..m + n {
+			out.0[timer - 1][timer] += 1u8;
+		}
+		out.0[m - 1][0] += 1u8;
+		out.0[m + n - 1][0] += 1u8;
+
+		out
+	}
+
+	fn mul(&self, other: &Self, modulus: Option<&BigUint>) -> Self {
+		let size = self.0.len();
+
+		Self(
+			(0..size)
 				.map(|i| {
-					if i == self.next {
-						Style::new().bold()
-							.paint(format!("{}+{}", self.queue[i], self.new[i]))
-							.to_string()
+					(0..size)
+						.map(|l| {
+							let value = (0..size).map(|k| &self.0[i][k] * &other.0[k][l]).sum::<BigUint>();
+
+							match modulus {
+								Some(modulus) => value % modulus,
+								None => value,
+							}
+						})
+						.collect()
+				})
+				.collect()
+		)
+	}
+
+	fn apply(&self, vector: &[BigUint], modulus: Option<&BigUint>) -> Vec<BigUint> {
+		self.0.iter()
+			.map(|row| {
+				let value = row.iter().zip(vector).map(|(a, b)| a * b).sum::<BigUint>();
+
+				match modulus {
+					Some(modulus) => value % modulus,
+					None => value,
+				}
+			})
+			.collect()
+	}
+
+	// Square and multiply, O(log exp) multiplications
+	fn pow(&self, mut exp: u64, modulus: Option<&BigUint>) -> Self {
+		let mut base = self.clone();
+		let mut out = Self::identity(self.0.len());
+
+		while exp > 0 {
+			if exp & 1 == 1 {
+				out = out.mul(&base, modulus);
+			}
+
+			exp >>= 1;
+			if exp > 0 {
+				base = base.mul(&base, modulus);
+			}
+		}
+
+		out
+	}
+}
+
+// Simulates a state one day at a time while the counts fit, afterwards (and for every day
+// under a modulus) the totals are computed from the initial state by matrix power
+#[derive(Debug)]
+struct Tracker {
+	initial: State,
+	state: State,
+	simulating: bool,
+}
+
+impl Tracker {
+	fn new(state: State, modulus: Option<&BigUint>) -> Self {
+		Self {
+			initial: state.clone(),
+			state,
+			simulating: modulus.is_none(),
+		}
+	}
+
+	// Days must be queried in increasing order, returns the simulated state if it is still exact
+	fn advance(&mut self, day: u64) -> Option<&State> {
+		while self.simulating && (self.state.day as u64) < day {
+			self.simulating = self.state.day().is_some();
+		}
+
+		if self.simulating { Some(&self.state) } else { None }
+	}
+
+	fn total(&mut self, day: u64, modulus: Option<&BigUint>) -> BigUint {
+		match self.advance(day) {
+			Some(state) => BigUint::from(state.total()),
+			None => self.initial.population(day, modulus),
+		}
+	}
+}
+
+impl fmt::Display for State {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} = {}",
+			self.timers().iter()
+				.enumerate()
+				.map(|(timer, count)| {
+					if timer == 0 {
+						Style::new().bold().paint(count.to_string()).to_string()
 					} else {
-						format!("{}+{}", self.queue[i], self.new[i])
+						count.to_string()
 					}
 				})
 				.collect::<Vec<String>>()
@@ -61,19 +248,141 @@ impl<const M: usize, const N: usize> ToString for State<M, N> {
 	}
 }
 
+fn exit_with(e: impl fmt::Display) -> ! {
+	eprintln!("{}", e);
+	process::exit(1);
+}
+
+// One row per day from 0 to `last`, with the fish count of every timer value and the total
+fn export_histogram(path: &str, state: &State, last: u64, modulus: Option<&BigUint>) -> io::Result<()> {
+	let mut out = BufWriter::new(File::create(path)?);
+	let transition = Matrix::transition(state.cycle, state.delay);
+	let mut timers: Vec<BigUint> = state.timers().into_iter().map(BigUint::from).collect();
+
+	let header: Vec<String> = (0..timers.len()).map(|timer| format!("timer_{}", timer)).collect();
+	writeln!(out, "day,{},total", header.join(","))?;
+
+	for day in 0..=last {
+		if day > 0 {
+			timers = transition.apply(&timers, modulus);
+		}
+
+		let total = timers.iter().sum::<BigUint>();
+		let total = match modulus {
+			Some(modulus) => total % modulus,
+			None => total,
+		};
+
+		let row: Vec<String> = timers.iter().map(|count| count.to_string()).collect();
+		writeln!(out, "{},{},{}", day, row.join(","), total)?;
+	}
+
+	out.flush()
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> T {
+	value.parse().unwrap_or_else(|_| exit_with(format!("invalid {}: {}", name, value)))
+}
+
 fn main() {
-	let mut state = State::<7, 2>::new();
+	// `cycle=<C>` and `delay=<D>` set the reproduction cycle and the extra days before newborns join it,
+	// `days=<a,b,...>` the queried days and `mod=<p>` reduces the answers modulo p,
+	// `lineage` reports the descendants of each initial fish and `csv=<path>` exports the timer histogram of every day
+	let mut cycle = 7;
+	let mut delay = 2;
+	let mut days = vec![80u64, 256];
+	let mut modulus = None::<BigUint>;
+	let mut lineage = false;
+	let mut csv = None::<String>;
+
+	for arg in env::args().skip(1) {
+		if arg == "lineage" {
+			lineage = true;
+			continue;
+		}
+
+		match arg.split_once('=') {
+			Some(("cycle", value)) => cycle = parse("cycle", value),
+			Some(("delay", value)) => delay = parse("delay", value),
+			Some(("days", value)) => days = value.split(',').map(|day| parse("day", day)).collect(),
+			Some(("mod", value)) => modulus = Some(parse("modulus", value)),
+			Some(("csv", value)) => csv = Some(value.to_string()),
+			_ => exit_with(format!("unknown argument: {}", arg)),
+		}
+	}
+
+	if cycle == 0 {
+		exit_with("cycle must be positive");
+	}
+	if modulus.as_ref().is_some_and(|modulus| *modulus == BigUint::from(0u8)) {
+		exit_with("modulus must be positive");
+	}
+	if let Some(&day) = days.iter().filter(|&&day| day > EXACT_LIMIT).max() {
+		if modulus.is_none() {
+			exit_with(format!("day {} is too large for an exact count, use mod=<p>", day));
+		}
+		if csv.is_some() {
+			exit_with(format!("day {} is too large for a histogram export", day));
+		}
+	}
+	if let Some(&day) = days.iter().filter(|&&day| day > EXACT_HISTOGRAM_LIMIT).max() {
+		if modulus.is_none() && csv.is_some() {
+			exit_with(format!("day {} is too large for an exact histogram export, use mod=<p>", day));
+		}
+	}
+
+	let mut state = State::new(cycle, delay);
 	let stdin = io::stdin();
 	let mut input = stdin.lock().lines();
 
-	for number in input.next().unwrap().unwrap().split(",") {
-		state.push(number.parse().unwrap());
+	for number in input.next().unwrap().unwrap().split(',') {
+		state.push(parse("timer", number)).unwrap_or_else(|e| exit_with(e));
+	}
+
+	println!("{}", state);
+
+	days.sort_unstable();
+	days.dedup();
+
+	if let Some(path) = &csv {
+		export_histogram(path, &state, *days.last().unwrap_or(&0), modulus.as_ref())
+			.unwrap_or_else(|e| exit_with(format!("{}: {}", path, e)));
 	}
 
-	println!("{}", state.to_string());
+	// One lineage per initial timer value, started from a single fish since all fish
+	// with the same timer have the same number of descendants
+	let mut lineages: Vec<(usize, u64, Tracker)> = if lineage {
+		state.timers().into_iter()
+			.enumerate()
+			.filter(|&(_, count)| count > 0)
+			.map(|(timer, count)| {
+				let mut single = State::new(cycle, delay);
+				single.push(timer).unwrap();
+				(timer, count, Tracker::new(single, modulus.as_ref()))
+			})
+			.collect()
+	} else {
+		vec![]
+	};
 
-	for day in 0..256 {
-		state.day();
-		println!("{}: {}", day, state.to_string());
+	let mut tracker = Tracker::new(state, modulus.as_ref());
+
+	for &day in days.iter() {
+		match (tracker.advance(day), &modulus) {
+			(Some(state), _) => println!("Day {}: {}", day, state),
+			(None, Some(modulus)) => println!("Day {}: {} (mod {})", day, tracker.total(day, Some(modulus)), modulus),
+			(None, None) => println!("Day {}: {}", day, tracker.total(day, None)),
+		}
+
+		for (timer, count, lineage) in lineages.iter_mut() {
+			// The fish itself is not its own descendant
+			let total = lineage.total(day, modulus.as_ref());
+			let descendants = match &modulus {
+				Some(modulus) => (total + modulus - 1u8) % modulus,
+				None => total - 1u8,
+			};
+
+			println!("  Initial timer {}: {} fish, {} descendants each", timer, count, descendants);
+		}
 	}
 }
@@ -1,38 +1,342 @@
-use std::io::{self, BufRead};
+use std::env;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::process;
 
-fn abs_diff(a: u32, b: u32) -> u32 {
-	if a < b {
-		b - a
-	} else {
-		a - b
+// The total cost of aligning on `target` does not fit in a u64
+#[derive(Debug)]
+struct Overflow {
+	target: i64,
+}
+
+impl fmt::Display for Overflow {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "total fuel for position {} overflows", self.target)
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Cost {
+	Linear,
+	Triangular,
+	// Any named fuel function of the distance that is convex and non-decreasing, None on overflow
+	Convex(&'static str, fn(u64) -> Option<u64>),
+}
+
+impl Cost {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Linear => "linear",
+			Self::Triangular => "triangular",
+			Self::Convex(name, _) => name,
+		}
+	}
+
+	fn fuel(self, a: i64, b: i64) -> Option<u64> {
+		let diff = a.abs_diff(b);
+
+		match self {
+			Self::Linear => Some(diff),
+			// Halve whichever factor is even before multiplying
+			Self::Triangular => if diff.is_multiple_of(2) {
+				(diff / 2).checked_mul(diff + 1)
+			} else {
+				diff.checked_mul(diff.div_ceil(2))
+			},
+			Self::Convex(_, fuel) => fuel(diff),
+		}
+	}
+
+	fn total(self, numbers: &[i64], target: i64) -> Result<u64, Overflow> {
+		self.probe(numbers, target).ok()
+			.and_then(|total| u64::try_from(total).ok())
+			.ok_or(Overflow { target })
+	}
+
+	// The total in 128 bits, which cannot overflow with 64-bit terms, so it is only infinite when the fuel
+	// of a single crab overflows. The error tells on which side of `target` that crab is: since the fuel
+	// never decreases with the distance, the targets with a finite total lie in that direction
+	fn probe(self, numbers: &[i64], target: i64) -> Result<u128, Ordering> {
+		numbers.iter().try_fold(0u128, |total, &number| match self.fuel(number, target) {
+			Some(fuel) => Ok(total + fuel as u128),
+			None => Err(number.cmp(&target)),
+		})
+	}
+
+	// Best (target, total), `numbers` must be sorted and non-empty
+	fn optimum(self, numbers: &[i64]) -> Result<(i64, u64), Overflow> {
+		let (min, max) = (numbers[0], numbers[numbers.len() - 1]);
+
+		let candidates = match self {
+			// Any median minimizes the sum of distances
+			Self::Linear => vec![numbers[numbers.len() / 2]],
+			// The optimum is within 1/2 of the mean
+			Self::Triangular => {
+				let mean = numbers.iter().map(|&number| number as i128).sum::<i128>().div_euclid(numbers.len() as i128);
+				let mean = mean as i64;
+
+				(mean.saturating_sub(1)..=mean.saturating_add(1)).filter(|target| (min..=max).contains(target)).collect()
+			},
+			// The total is convex in the target, ternary search keeps the side holding the minimum,
+			// an infinite probe only tells which side the finite totals are on
+			Self::Convex(..) => {
+				let (mut low, mut high) = (min, max);
+
+				while high.abs_diff(low) > 2 {
+					let third = (high.abs_diff(low) / 3) as i64;
+					let left = low + third;
+					let right = high - third;
+
+					let rightwards = match (self.probe(numbers, left), self.probe(numbers, right)) {
+						(Err(Ordering::Greater), _) => true,
+						(Ok(left), Ok(right)) => left > right,
+						_ => false,
+					};
+
+					if rightwards {
+						low = left;
+					} else {
+						high = right;
+					}
+				}
+
+				(low..=high).collect()
+			},
+		};
+
+		// Candidates that overflow are skipped, only the chosen optimum has to fit
+		let mut best = None::<(i64, u128)>;
+		for &target in candidates.iter() {
+			let total = match self.probe(numbers, target) {
+				Ok(total) => total,
+				Err(_) => continue,
+			};
+
+			if best.is_none_or(|(_, best)| total < best) {
+				best = Some((target, total));
+			}
+		}
+
+		let (target, total) = best.ok_or(Overflow { target: candidates[0] })?;
+		Ok((target, u64::try_from(total).map_err(|_| Overflow { target })?))
+	}
+
+	// Splits the sorted crabs into `k` contiguous groups, each aligning on its own optimum, which
+	// is optimal since every crab is closest to the target of its group. Returns the total and the
+	// target of every group, or None if every split overflows
+	fn groups(self, numbers: &[i64], k: usize) -> Option<(u64, Vec<i64>)> {
+		let n = numbers.len();
+
+		// optimum[i][j] for the group numbers[i..j]
+		let prefix = Prefix::new(numbers);
+		let optimum: Vec<Vec<Option<(i64, u64)>>> = (0..n)
+			.map(|i| {
+				(0..=n)
+					.map(|j| if j > i { prefix.optimum(self, numbers, i, j) } else { None })
+					.collect()
+			})
+			.collect();
+
+		// best[g][j] is the lowest total for numbers[..j] in g groups, with the start of the last group
+		let mut best = vec![vec![None::<(u64, usize)>; n + 1]; k + 1];
+		best[0][0] = Some((0, 0));
+
+		for g in 1..=k {
+			for j in g..=n {
+				best[g][j] = (g - 1..j)
+					.filter_map(|i| {
+						let (total, _) = best[g - 1][i]?;
+						let (_, group) = optimum[i][j]?;
+						Some((total.checked_add(group)?, i))
+					})
+					.min_by_key(|&(total, _)| total);
+			}
+		}
+
+		let (total, _) = best[k][n]?;
+		let mut targets = vec![];
+		let mut j = n;
+
+		for g in (1..=k).rev() {
+			let (_, i) = best[g][j].unwrap();
+			targets.push(optimum[i][j].unwrap().0);
+			j = i;
+		}
+
+		targets.reverse();
+		Some((total, targets))
 	}
 }
 
-fn fuel(a: u32, b: u32) -> u32 {
-	let diff = abs_diff(a, b);
-	return (diff * diff + diff) / 2;
+// Prefix sums of the sorted crabs, measured from the first one so they are never negative, giving the
+// linear and triangular totals of any contiguous group without visiting its crabs
+#[derive(Debug)]
+struct Prefix {
+	origin: i64,
+	sums: Vec<u128>,
+	// None once the squares no longer fit
+	squares: Vec<Option<u128>>,
+}
+
+impl Prefix {
+	// `numbers` must be sorted and non-empty
+	fn new(numbers: &[i64]) -> Self {
+		let origin = numbers[0];
+		let mut sums = vec![0];
+		let mut squares = vec![Some(0)];
+
+		for &number in numbers {
+			let offset = number.abs_diff(origin) as u128;
+			sums.push(sums[sums.len() - 1] + offset);
+			squares.push(squares[squares.len() - 1].and_then(|sum: u128| sum.checked_add(offset.checked_mul(offset)?)));
+		}
+
+		Self {
+			origin,
+			sums,
+			squares,
+		}
+	}
+
+	fn offset(&self, target: i64) -> u128 {
+		target.abs_diff(self.origin) as u128
+	}
+
+	// Sum of distances from `target` over numbers[i..j], `target` must not be below the origin
+	fn distances(&self, numbers: &[i64], i: usize, j: usize, target: i64) -> u128 {
+		let offset = self.offset(target);
+		let split = i + numbers[i..j].partition_point(|&number| number < target);
+
+		let below = (split - i) as u128 * offset - (self.sums[split] - self.sums[i]);
+		let above = (self.sums[j] - self.sums[split]) - (j - split) as u128 * offset;
+		below + above
+	}
+
+	// Sum of squared distances from `target` over numbers[i..j], expanded as Σx² - 2tΣx + nt²
+	fn squares(&self, i: usize, j: usize, target: i64) -> Option<u128> {
+		let offset = self.offset(target);
+		let squares = self.squares[j]? - self.squares[i]?;
+		let sum = self.sums[j] - self.sums[i];
+
+		squares
+			.checked_add(((j - i) as u128).checked_mul(offset.checked_mul(offset)?)?)?
+			.checked_sub(offset.checked_mul(sum)?.checked_mul(2)?)
+	}
+
+	// Same result as `Cost::optimum` on numbers[i..j], which is still used for convex costs
+	// and for groups too far apart for the squares to fit
+	fn optimum(&self, cost: Cost, numbers: &[i64], i: usize, j: usize) -> Option<(i64, u64)> {
+		let candidates = match cost {
+			Cost::Linear => vec![numbers[i + (j - i) / 2]],
+			Cost::Triangular => {
+				let mean = self.origin + ((self.sums[j] - self.sums[i]) / (j - i) as u128) as i64;
+				(mean.saturating_sub(1)..=mean.saturating_add(1)).filter(|target| (numbers[i]..=numbers[j - 1]).contains(target)).collect()
+			},
+			Cost::Convex(..) => return cost.optimum(&numbers[i..j]).ok(),
+		};
+
+		let mut best = None::<(i64, u128)>;
+		for target in candidates {
+			let distances = self.distances(numbers, i, j, target);
+			let total = match cost {
+				Cost::Triangular => match self.squares(i, j, target).and_then(|squares| squares.checked_add(distances)) {
+					Some(total) => total / 2,
+					None => return cost.optimum(&numbers[i..j]).ok(),
+				},
+				_ => distances,
+			};
+
+			if best.is_none_or(|(_, best)| total < best) {
+				best = Some((target, total));
+			}
+		}
+
+		let (target, total) = best?;
+		Some((target, u64::try_from(total).ok()?))
+	}
+}
+
+// Positions beyond this span are not exported
+const CURVE_LIMIT: u64 = 10_000_000;
+
+// One row per position between the outermost crabs, with the total of every cost model
+// (empty on overflow)
+fn export_curve(path: &str, numbers: &[i64], costs: &[Cost]) -> io::Result<()> {
+	let mut out = BufWriter::new(File::create(path)?);
+	let names: Vec<&str> = costs.iter().map(|cost| cost.name()).collect();
+	writeln!(out, "position,{}", names.join(","))?;
+
+	for target in numbers[0]..=numbers[numbers.len() - 1] {
+		let totals: Vec<String> = costs.iter()
+			.map(|cost| cost.total(numbers, target).map_or(String::new(), |total| total.to_string()))
+			.collect();
+
+		writeln!(out, "{},{}", target, totals.join(","))?;
+	}
+
+	out.flush()
+}
+
+fn exit_with(e: impl fmt::Display) -> ! {
+	eprintln!("{}", e);
+	process::exit(1);
 }
 
 fn main() {
-	let mut numbers = Vec::<u32>::new();
+	let mut numbers = Vec::<i64>::new();
 	let stdin = io::stdin();
 	let mut input = stdin.lock().lines();
 
-	for number in input.next().unwrap().unwrap().split(",") {
-		numbers.push(number.parse().unwrap());
+	for number in input.next().unwrap().unwrap().split(',') {
+		numbers.push(number.trim().parse().unwrap_or_else(|_| exit_with(format!("Invalid position: {:?}", number))));
 	}
 
-	let max = numbers.iter().max().unwrap();
-	let mut range = vec![0; *max as usize + 1];
+	numbers.sort_unstable();
 
-	for number in numbers.iter() {
-		for (i, target) in range.iter_mut().enumerate() {
-			*target += fuel(*number, i as u32)
+	// Extra convex cost models can be requested by name, `csv=<path>` exports the total cost
+	// by position and `groups=<k>` also aligns the crabs on k positions
+	let mut costs = vec![Cost::Linear, Cost::Triangular];
+	let mut csv = None::<String>;
+	let mut groups = None::<usize>;
+
+	for arg in env::args().skip(1) {
+		match arg.split_once('=') {
+			Some(("csv", path)) => csv = Some(path.to_string()),
+			Some(("groups", k)) => groups = Some(k.parse().ok().filter(|&k| k > 0).unwrap_or_else(|| exit_with(format!("Invalid group count: {}", k)))),
+			_ => match arg.as_str() {
+				"quadratic" => costs.push(Cost::Convex("quadratic", |diff| diff.checked_mul(diff))),
+				"cubic" => costs.push(Cost::Convex("cubic", |diff| diff.checked_mul(diff)?.checked_mul(diff))),
+				_ => exit_with(format!("Unknown cost model: {}", arg)),
+			},
 		}
 	}
 
-	let (target, distances) = range.iter().enumerate()
-		.min_by_key(|&(_, val)| val)
-		.unwrap();
-	println!("{}, {}", target, distances);
+	for cost in costs.iter() {
+		match cost.optimum(&numbers) {
+			Ok((target, distances)) => println!("{}: {}, {}", cost.name(), target, distances),
+			Err(e) => println!("{}: {}", cost.name(), e),
+		}
+	}
+
+	if let Some(k) = groups {
+		let k = k.min(numbers.len());
+
+		for cost in costs.iter() {
+			match cost.groups(&numbers, k) {
+				Some((distances, targets)) => println!("{} in {} groups: {:?}, {}", cost.name(), k, targets, distances),
+				None => println!("{} in {} groups: total fuel overflows", cost.name(), k),
+			}
+		}
+	}
+
+	if let Some(path) = csv {
+		if numbers[0].abs_diff(numbers[numbers.len() - 1]) > CURVE_LIMIT {
+			exit_with(format!("Positions span more than {} for the cost curve export", CURVE_LIMIT));
+		}
+
+		export_curve(&path, &numbers, &costs).unwrap_or_else(|e| exit_with(format!("{}: {}", path, e)));
+	}
 }
@@ -1,224 +1,494 @@
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io::{self, BufRead};
+use std::process;
 use std::str::FromStr;
-use std::collections::{HashMap, HashSet};
-use lazy_static::lazy_static;
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-enum Segment {
-	A, B, C, D, E, F, G,
+// Segments are named by letters from `a`, up to sixteen-segment displays
+const MAX_SEGMENTS: usize = 16;
+
+// Glyph tables, one `<symbol> <segments>` per line, segment `a` is the top one
+const DECIMAL: &str = "
+	0 abcefg
+	1 cf
+	2 acdeg
+	3 acdfg
+	4 bcdf
+	5 abdfg
+	6 abdefg
+	7 acf
+	8 abcdefg
+	9 abcdfg
+";
+
+const HEX_LETTERS: &str = "
+	A abcdef
+	B bdefg
+	C abeg
+	D cdefg
+	E abdeg
+	F abde
+";
+
+// Fourteen segments: the seven above with the middle split into `d` (left) and `h` (right), plus
+// the diagonals `i`, `k` (upper left and right), `l`, `n` (lower left and right) and the center
+// verticals `j` (upper) and `m` (lower)
+const ALPHANUMERIC_14: &str = "
+	0 abcefgkl
+	1 cfk
+	2 acdegh
+	3 acfgh
+	4 bcdfh
+	5 abdfgh
+	6 abdefgh
+	7 acf
+	8 abcdefgh
+	9 abcdfgh
+	A abcdefh
+	B acfghjm
+	C abeg
+	D acfgjm
+	E abdeg
+	F abde
+	G abefgh
+	H bcdefh
+	I agjm
+	J cefg
+	K bdekn
+	L beg
+	M bcefik
+	N bcefin
+	O abcefg
+	P abcdeh
+	Q abcefgn
+	R abcdehn
+	S afghi
+	T ajm
+	U bcefg
+	V bekl
+	W bcefln
+	X ikln
+	Y ikm
+	Z agkl
+";
+
+// Sixteen segments: the fourteen above with the top split into `a` (left) and `o` (right)
+// and the bottom split into `g` (left) and `p` (right), every glyph lights both halves
+const ALPHANUMERIC_16: &str = "
+	0 abcefgklop
+	1 cfk
+	2 acdeghop
+	3 acfghop
+	4 bcdfh
+	5 abdfghop
+	6 abdefghop
+	7 acfo
+	8 abcdefghop
+	9 abcdfghop
+	A abcdefho
+	B acfghjmop
+	C abegop
+	D acfgjmop
+	E abdegop
+	F abdeo
+	G abefghop
+	H bcdefh
+	I agjmop
+	J cefgp
+	K bdekn
+	L begp
+	M bcefik
+	N bcefin
+	O abcefgop
+	P abcdeho
+	Q abcefgnop
+	R abcdehno
+	S afghiop
+	T ajmo
+	U bcefgp
+	V bekl
+	W bcefln
+	X ikln
+	Y ikm
+	Z agklop
+";
+
+#[derive(Debug, Copy, Clone)]
+struct Glyph {
+	symbol: char,
+	segments: u16,
 }
 
+#[derive(Debug)]
+struct Alphabet {
+	segments: usize,
+	glyphs: Vec<Glyph>,
+}
+
+// mapping[wire] is the segment driven by that wire
 #[derive(Debug)]
 struct Display {
-	mapping: HashMap<Segment, Segment>
+	segments: usize,
+	mapping: [u8; MAX_SEGMENTS],
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 struct DisplayedNumber {
-	segments: HashSet<Segment>,
-}
-
-impl Segment {
-	fn from_char(input: char) -> Result<Self, ()> {
-		match input.to_ascii_uppercase() {
-			'A' => Ok(Self::A),
-			'B' => Ok(Self::B),
-			'C' => Ok(Self::C),
-			'D' => Ok(Self::D),
-			'E' => Ok(Self::E),
-			'F' => Ok(Self::F),
-			'G' => Ok(Self::G),
-			_ => Err(()),
+	segments: u16,
+}
+
+#[derive(Debug)]
+enum SolveError {
+	NoMapping,
+	Ambiguous,
+}
+
+impl fmt::Display for SolveError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::NoMapping => write!(f, "no mapping"),
+			Self::Ambiguous => write!(f, "ambiguous mapping"),
 		}
 	}
 }
 
+fn segment_from_char(input: char) -> Result<usize, ()> {
+	match input.to_ascii_lowercase() {
+		c @ 'a'..='p' => Ok(c as usize - 'a' as usize),
+		_ => Err(()),
+	}
+}
+
 impl FromStr for DisplayedNumber {
 	type Err = ();
 
 	fn from_str(input: &str) -> Result<Self, Self::Err> {
-		Ok(Self {
-			segments: input
-				.chars()
-				.take(7)
-				.map(|c| Segment::from_char(c).unwrap())
-				.collect()
+		input.chars().try_fold(Self::new(0), |number, c| {
+			Ok(Self::new(number.segments | 1 << segment_from_char(c)?))
 		})
 	}
 }
 
 impl DisplayedNumber {
-	fn new(segments: HashSet<Segment>) -> Self {
+	fn new(segments: u16) -> Self {
 		Self {
-			segments: segments,
+			segments,
 		}
 	}
 
-	fn to_int(&self) -> Result<u8, ()> {
-		lazy_static! {
-			static ref NUMBERS: [DisplayedNumber; 10] = [
-				DisplayedNumber::from_str("ABCEFG").unwrap(),
-				DisplayedNumber::from_str("CF").unwrap(),
-				DisplayedNumber::from_str("ACDEG").unwrap(),
-				DisplayedNumber::from_str("ACDFG").unwrap(),
-				DisplayedNumber::from_str("BCDF").unwrap(),
-				DisplayedNumber::from_str("ABDFG").unwrap(),
-				DisplayedNumber::from_str("ABDEFG").unwrap(),
-				DisplayedNumber::from_str("ACF").unwrap(),
-				DisplayedNumber::from_str("ABCDEFG").unwrap(),
-				DisplayedNumber::from_str("ABCDFG").unwrap(),
-			];
-		}
+	fn len(self) -> u32 {
+		self.segments.count_ones()
+	}
+
+	fn to_glyph(self, alphabet: &Alphabet) -> Option<usize> {
+		alphabet.glyphs.iter().position(|glyph| glyph.segments == self.segments)
+	}
+}
+
+impl FromStr for Alphabet {
+	type Err = String;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let mut glyphs = Vec::<Glyph>::new();
+
+		for line in input.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+			let (symbol, segments) = line.split_once(char::is_whitespace)
+				.ok_or_else(|| format!("invalid glyph {:?}, expected <symbol> <segments>", line))?;
 
-		for (i, number) in NUMBERS.iter().enumerate() {
-			if self == number {
-				return Ok(i as u8);
+			let mut chars = symbol.chars();
+			let symbol = match (chars.next(), chars.next()) {
+				(Some(symbol), None) => symbol,
+				_ => return Err(format!("glyph symbol {:?} is not a single character", symbol)),
+			};
+
+			let segments = DisplayedNumber::from_str(segments.trim())
+				.map_err(|_| format!("invalid segments for glyph {:?}", symbol))?
+				.segments;
+
+			if let Some(other) = glyphs.iter().find(|glyph| glyph.segments == segments || glyph.symbol == symbol) {
+				return Err(format!("glyphs {:?} and {:?} are not distinct", other.symbol, symbol));
 			}
+
+			glyphs.push(Glyph { symbol, segments });
+		}
+
+		let segments = glyphs.iter()
+			.map(|glyph| MAX_SEGMENTS - glyph.segments.leading_zeros() as usize)
+			.max()
+			.ok_or("empty glyph table")?;
+
+		Ok(Self {
+			segments,
+			glyphs,
+		})
+	}
+}
+
+impl Alphabet {
+	fn all(&self) -> u16 {
+		((1u32 << self.segments) - 1) as u16
+	}
+
+	// Numeric alphabets must list their digits in order, like 0-9 or 0-F
+	fn radix(&self) -> Result<u64, String> {
+		let radix = self.glyphs.len() as u32;
+		let digits = (2..=36).contains(&radix) && self.glyphs.iter()
+			.enumerate()
+			.all(|(i, glyph)| glyph.symbol.to_digit(radix) == Some(i as u32));
+
+		if digits {
+			Ok(radix as u64)
+		} else {
+			Err("numeric glyphs must be the digits of a base from 2 to 36 in order".to_string())
 		}
+	}
 
-		Err(())
+	// Glyphs identified by their segment count alone, 1, 4, 7 and 8 for the decimal digits
+	fn unique_length(&self, number: DisplayedNumber) -> bool {
+		self.glyphs.iter().filter(|glyph| glyph.segments.count_ones() == number.len()).count() == 1
 	}
 }
 
+// Partial search state, `assigned` and `used` are the masks of mapped wires and segments
+#[derive(Debug, Copy, Clone)]
+struct Search {
+	mapping: [u8; MAX_SEGMENTS],
+	assigned: u16,
+	used: u16,
+}
+
 impl Display {
-	fn new(numbers: Vec<DisplayedNumber>) -> Self {
-		let numbers: Vec<DisplayedNumber> = numbers.into_iter().take(10).collect();
-		let mut mapping = HashMap::<Segment, Segment>::with_capacity(7);
-
-		let number_1 = numbers.iter()
-			.filter(|n| n.segments.len() == 2)
-			.next().unwrap();
-
-		let number_4 = numbers.iter()
-			.filter(|n| n.segments.len() == 4)
-			.next().unwrap();
-
-		let number_7 = numbers.iter()
-			.filter(|n| n.segments.len() == 3)
-			.next().unwrap();
-
-		// Mapping for segment A
-		mapping.insert(
-			number_7.segments.iter()
-				.filter(|s| !number_1.segments.contains(s))
-				.cloned().next().unwrap(),
-			Segment::A,
-		);
-
-		// Candidates for segments C or F
-		let seg_c_f: Vec<&Segment> = number_1.segments.iter().collect();
-
-		// Candidates for segments B or D
-		let seg_b_d: Vec<&Segment> = number_4.segments.iter()
-			.filter(|s| !number_1.segments.contains(s))
-			.collect();
+	// Searches all wire to segment permutations, `numbers` are every pattern seen on the display
+	fn new(alphabet: &Alphabet, numbers: &[DisplayedNumber]) -> Result<Self, SolveError> {
+		let mut solutions = (0, [0; MAX_SEGMENTS]);
+		let search = Search { mapping: [0; MAX_SEGMENTS], assigned: 0, used: 0 };
+		Self::solve(alphabet, numbers, search, &mut solutions);
+
+		match solutions {
+			(0, _) => Err(SolveError::NoMapping),
+			(1, mapping) => Ok(Self {
+				segments: alphabet.segments,
+				mapping,
+			}),
+			_ => Err(SolveError::Ambiguous),
+		}
+	}
 
-		let number_2 = numbers.iter()
-			.filter(|n| n.segments.len() == 5)
-			.filter(|n| n.segments.iter().filter(|s| seg_c_f.contains(s)).count() == 1)
-			.filter(|n| n.segments.iter().filter(|s| seg_b_d.contains(s)).count() == 1)
-			.next().unwrap();
-
-		// Mapping for segment B
-		let seg_b = seg_b_d.iter()
-			.filter(|s| !number_2.segments.contains(s))
-			.cloned().next().unwrap().clone();
-		mapping.insert(seg_b, Segment::B);
-
-		// Mapping for segment D
-		mapping.insert(
-			seg_b_d.iter()
-				.filter(|s| number_2.segments.contains(s))
-				.cloned().next().unwrap().clone(),
-			Segment::D,
-		);
-
-		// Mapping for segment C
-		mapping.insert(
-			seg_c_f.iter()
-				.filter(|s| number_2.segments.contains(s))
-				.cloned().next().unwrap().clone(),
-			Segment::C,
-		);
-
-		// Mapping for segment F
-		mapping.insert(
-			seg_c_f.iter()
-				.filter(|s| !number_2.segments.contains(s))
-				.cloned().next().unwrap().clone(),
-			Segment::F,
-		);
-
-		let number_5 = numbers.iter()
-			.filter(|n| n.segments.len() == 5)
-			.filter(|n| n.segments.contains(&seg_b))
-			.next().unwrap();
-
-		// Mapping for segment G
-		mapping.insert(
-			number_5.segments.iter()
-				.filter(|s| !mapping.contains_key(*s))
-				.cloned().next().unwrap().clone(),
-			Segment::G,
-		);
-
-		// Mapping for segment E
-		mapping.insert(
-			number_2.segments.iter()
-				.filter(|s| !mapping.contains_key(*s))
-				.cloned().next().unwrap().clone(),
-			Segment::E,
-		);
+	// Assigns one wire at a time, stops after a second solution since that already makes it ambiguous.
+	// Segments that are always lit together, like the halves of a sixteen-segment bar, can be swapped
+	// without changing any glyph, so only a solution reading some pattern differently counts as another one
+	fn solve(alphabet: &Alphabet, numbers: &[DisplayedNumber], search: Search, solutions: &mut (usize, [u8; MAX_SEGMENTS])) {
+		if solutions.0 > 1 {
+			return;
+		}
 
-		Self {
-			mapping: mapping,
+		let wire = search.assigned.trailing_ones() as usize;
+		if wire == alphabet.segments {
+			let (count, mapping) = *solutions;
+			let differs = numbers.iter().any(|number| Self::map(&mapping, number.segments) != Self::map(&search.mapping, number.segments));
+
+			if count == 0 || differs {
+				*solutions = (count + 1, search.mapping);
+			}
+			return;
 		}
+
+		for segment in 0..alphabet.segments as u8 {
+			if search.used & 1 << segment != 0 {
+				continue;
+			}
+
+			let mut next = search;
+			next.mapping[wire] = segment;
+			next.assigned |= 1 << wire;
+			next.used |= 1 << segment;
+
+			if Self::consistent(alphabet, numbers, &next) {
+				Self::solve(alphabet, numbers, next, solutions);
+			}
+		}
+	}
+
+	fn map(mapping: &[u8; MAX_SEGMENTS], wires: u16) -> u16 {
+		(0..MAX_SEGMENTS)
+			.filter(|wire| wires & 1 << wire != 0)
+			.fold(0, |segments, wire| segments | 1 << mapping[wire])
 	}
 
-	fn convert(&self, number: &DisplayedNumber) -> DisplayedNumber {
-		DisplayedNumber::new(
-			number.segments.iter()
-				.map(|s| self.mapping[s])
-				.collect(),
-		)
+	// Every pattern must still fit a glyph of the same size on the wires assigned so far
+	fn consistent(alphabet: &Alphabet, numbers: &[DisplayedNumber], search: &Search) -> bool {
+		numbers.iter().all(|number| {
+			let lit = Self::map(&search.mapping, number.segments & search.assigned);
+			let dark = Self::map(&search.mapping, !number.segments & search.assigned);
+
+			alphabet.glyphs.iter().any(|glyph| {
+				glyph.segments.count_ones() == number.len() && glyph.segments & lit == lit && glyph.segments & dark == 0
+			})
+		})
+	}
+
+	fn convert(&self, number: DisplayedNumber) -> DisplayedNumber {
+		DisplayedNumber::new(Self::map(&self.mapping, number.segments))
 	}
 }
 
+impl fmt::Display for Display {
+	// Format: `<wires> -> <segments>`, e.g. `abcdefg -> dgebacf`
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let letter = |i: usize| (b'a' + i as u8) as char;
+
+		let wires: String = (0..self.segments).map(letter).collect();
+		let segments: String = self.mapping[..self.segments].iter().map(|&s| letter(s as usize)).collect();
+
+		write!(f, "{} -> {}", wires, segments)
+	}
+}
+
+// Character cells of a seven-segment digit, three rows of three, as (row, column, segment, char)
+const SEVEN_SEGMENT_ART: [(usize, usize, usize, char); 7] = [
+	(0, 1, 0, '_'),
+	(1, 0, 1, '|'),
+	(1, 2, 2, '|'),
+	(1, 1, 3, '_'),
+	(2, 0, 4, '|'),
+	(2, 2, 5, '|'),
+	(2, 1, 6, '_'),
+];
+
+// Draws the patterns next to each other, wires are drawn as the segment of the same letter
+fn render(numbers: &[DisplayedNumber]) -> [String; 3] {
+	let mut rows = [String::new(), String::new(), String::new()];
+
+	for (i, number) in numbers.iter().enumerate() {
+		let mut cells = [[' '; 3]; 3];
+		for &(row, column, segment, c) in SEVEN_SEGMENT_ART.iter() {
+			if number.segments & 1 << segment != 0 {
+				cells[row][column] = c;
+			}
+		}
+
+		for (row, cells) in rows.iter_mut().zip(cells.iter()) {
+			if i > 0 {
+				row.push(' ');
+			}
+			row.extend(cells.iter());
+		}
+	}
+
+	rows
+}
+
+fn exit_with(e: impl fmt::Display) -> ! {
+	eprintln!("{}", e);
+	process::exit(1);
+}
+
 fn main() {
-	let mut count_1478 = 0u32;
-	let mut sum = 0u32;
+	// `alphabet=decimal|hex|14-segment|16-segment` picks a built-in glyph table, `glyphs=<path>` loads one.
+	// Outputs are only read as numbers and summed for the decimal and hex tables, or with `numeric`
+	let mut alphabet = Alphabet::from_str(DECIMAL).unwrap();
+	let mut numeric = true;
+	let mut force_numeric = false;
+
+	for arg in env::args().skip(1) {
+		(alphabet, numeric) = match arg.split_once('=') {
+			Some(("alphabet", "decimal")) => (Alphabet::from_str(DECIMAL).unwrap(), true),
+			Some(("alphabet", "hex")) => (Alphabet::from_str(&format!("{}{}", DECIMAL, HEX_LETTERS)).unwrap(), true),
+			Some(("alphabet", "14-segment")) => (Alphabet::from_str(ALPHANUMERIC_14).unwrap(), false),
+			Some(("alphabet", "16-segment")) => (Alphabet::from_str(ALPHANUMERIC_16).unwrap(), false),
+			Some(("glyphs", path)) => {
+				let alphabet = fs::read_to_string(path)
+					.map_err(|e| e.to_string())
+					.and_then(|table| Alphabet::from_str(&table))
+					.unwrap_or_else(|e| exit_with(format!("{}: {}", path, e)));
+				(alphabet, false)
+			},
+			None if arg == "numeric" => {
+				force_numeric = true;
+				continue;
+			},
+			_ => exit_with(format!("Unknown argument: {}", arg)),
+		};
+	}
+
+	let radix = if numeric || force_numeric {
+		Some(alphabet.radix().unwrap_or_else(|e| exit_with(e)))
+	} else {
+		None
+	};
+	let mut unique_count = 0u64;
+	let mut sum = 0u64;
 
-	for line in io::stdin().lock().lines() {
+	for (i, line) in io::stdin().lock().lines().enumerate() {
 		let line = line.unwrap();
-		let mut split = line.split("|");
+		let mut split = line.split('|');
+
+		let parse = |part: Option<&str>| -> Vec<DisplayedNumber> {
+			part.unwrap_or_default()
+				.split_whitespace()
+				.map(|d| {
+					DisplayedNumber::from_str(d).ok()
+						.filter(|d| d.segments & !alphabet.all() == 0)
+						.unwrap_or_else(|| exit_with(format!("Line {}: invalid pattern {:?}", i + 1, d)))
+				})
+				.collect()
+		};
 
-		let example_numbers: Vec<DisplayedNumber> = split.next().unwrap()
-			.split_whitespace()
-			.map(|d| DisplayedNumber::from_str(d).unwrap())
-			.collect();
+		let example_numbers = parse(split.next());
+		let output_numbers = parse(split.next());
 
-		let display = Display::new(example_numbers);
+		// Part one only looks at pattern lengths, so it does not depend on decoding
+		unique_count += output_numbers.iter()
+			.filter(|&&d| alphabet.unique_length(d))
+			.count() as u64;
 
-		let numbers: Vec<u8> = split.next().unwrap()
-			.split_whitespace()
-			.map(|d| DisplayedNumber::from_str(d).unwrap())
-			.map(|d| display.convert(&d))
-			.map(|d| d.to_int().unwrap())
+		let all_numbers: Vec<DisplayedNumber> = example_numbers.into_iter()
+			.chain(output_numbers.iter().copied())
 			.collect();
 
-		count_1478 += numbers.iter()
-			.filter(|n| [1, 4, 7, 8].contains(n))
-			.count() as u32;
+		let display = match Display::new(&alphabet, &all_numbers) {
+			Ok(display) => display,
+			Err(e) => {
+				println!("Line {}: {}", i + 1, e);
+				continue;
+			},
+		};
 
-		let number = numbers.iter()
-			.enumerate()
-			.map(|(i, n)| *n as u32 * 10u32.pow(3 - i as u32))
-			.sum::<u32>();
-
-		sum += number;
+		let corrected: Vec<DisplayedNumber> = output_numbers.iter()
+			.map(|&d| display.convert(d))
+			.collect();
+		let numbers: Vec<usize> = corrected.iter()
+			.map(|d| d.to_glyph(&alphabet).unwrap())
+			.collect();
+		let text: String = numbers.iter().map(|&n| alphabet.glyphs[n].symbol).collect();
+
+		let number = radix.map(|radix| {
+			numbers.iter()
+				.try_fold(0u64, |number, &n| number.checked_mul(radix)?.checked_add(n as u64))
+				.and_then(|number| Some((number, sum.checked_add(number)?)))
+		});
+
+		match number {
+			None => println!("{}, {}", display, text),
+			Some(Some((number, new_sum))) => {
+				sum = new_sum;
+				println!("{}, {}, {}", display, text, number);
+			},
+			Some(None) => println!("Line {}: output {} overflows", i + 1, text),
+		}
 
-		println!("{:?}, {:?}, {}", display, numbers, number);
+		// Only seven-segment alphabets have a picture, the scrambled wiring is on the left
+		if alphabet.segments <= SEVEN_SEGMENT_ART.len() {
+			let separators = ["    ", " -> ", "    "];
+			for ((scrambled, decoded), separator) in render(&output_numbers).iter().zip(render(&corrected).iter()).zip(separators.iter()) {
+				println!("{}{}{}", scrambled, separator, decoded);
+			}
+		}
 	}
 
-	println!("Count 1478: {}, Sum: {}", count_1478, sum);
+	println!("Unique length count: {}", unique_count);
+	if radix.is_some() {
+		println!("Sum: {}", sum);
+	}
 }
@@ -1,5 +1,7 @@
+use std::cmp::Reverse;
+use std::fmt;
 use std::io::{self, BufRead};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use ansi_term::{Style, Colour::Green};
 
 type Point = u32;
@@ -11,6 +13,18 @@ enum Dir {
 	L, R, U, D,
 }
 
+#[derive(Debug)]
+struct Basin {
+	low: Coord,
+	cells: Vec<Coord>,
+}
+
+impl Basin {
+	fn size(&self) -> usize {
+		self.cells.len()
+	}
+}
+
 #[derive(Debug)]
 struct Map(Vec<Vec<Point>>);
 
@@ -81,41 +95,64 @@ impl Map {
 		self.0[coord.0][coord.1]
 	}
 
-	fn expand_basin(&self, set: CoordSet) -> CoordSet {
-		set.into_iter()
-			.map(|coord| {
-				[
-					self.move_coord(coord, Dir::L).filter(|v| self.find(*v) != 9),
-					self.move_coord(coord, Dir::R).filter(|v| self.find(*v) != 9),
-					self.move_coord(coord, Dir::U).filter(|v| self.find(*v) != 9),
-					self.move_coord(coord, Dir::D).filter(|v| self.find(*v) != 9),
-					Some(coord),
-				].iter().filter_map(|v| *v).collect::<CoordSet>()
-			})
-			.flatten()
-			.collect()
+	fn neighbours(&self, coord: Coord) -> impl Iterator<Item = Coord> + '_ {
+		IntoIterator::into_iter([Dir::L, Dir::R, Dir::U, Dir::D])
+			.filter_map(move |dir| self.move_coord(coord, dir))
 	}
 
-	fn basins(&self, minimums: &CoordSet) -> Vec<CoordSet> {
-		minimums.iter()
-			.map(|min| {
-				let mut basin = CoordSet::from([*min]);
-				let mut last_size = 0;
+	// Breadth-first flood from every low point over a shared label grid, so each cell is visited once,
+	// a low point already reached from another one shares its basin instead of starting a new one
+	fn basins(&self, minimums: &CoordSet) -> Vec<Basin> {
+		let mut labelled: Vec<Vec<bool>> = self.0.iter().map(|row| vec![false; row.len()]).collect();
+		let mut lows: Vec<Coord> = minimums.iter().copied().collect();
+		lows.sort_unstable();
+
+		lows.into_iter()
+			.filter_map(|low| {
+				if labelled[low.0][low.1] {
+					return None;
+				}
+
+				let mut basin = Basin { low, cells: vec![] };
+				let mut queue = VecDeque::from([low]);
+				labelled[low.0][low.1] = true;
+
+				while let Some(coord) = queue.pop_front() {
+					basin.cells.push(coord);
 
-				while last_size != basin.len() {
-					last_size = basin.len();
-					basin = self.expand_basin(basin);
+					for next in self.neighbours(coord) {
+						if !labelled[next.0][next.1] && self.find(next) != 9 {
+							labelled[next.0][next.1] = true;
+							queue.push_back(next);
+						}
+					}
 				}
 
-				basin
+				Some(basin)
 			})
 			.collect()
 	}
 }
 
-impl ToString for Map {
-	fn to_string(&self) -> String {
-		self.highlight(&[].into())
+// Keeps a min-heap of the `k` largest basins seen so far, largest first in the output
+fn top_basins(basins: &[Basin], k: usize) -> Vec<&Basin> {
+	let mut heap = BinaryHeap::with_capacity(k + 1);
+
+	for (i, basin) in basins.iter().enumerate() {
+		heap.push(Reverse((basin.size(), i)));
+		if heap.len() > k {
+			heap.pop();
+		}
+	}
+
+	heap.into_sorted_vec().into_iter()
+		.map(|Reverse((_, i))| &basins[i])
+		.collect()
+}
+
+impl fmt::Display for Map {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.highlight(&[].into()))
 	}
 }
 
@@ -136,16 +173,14 @@ fn main() {
 		.collect();
 	let risk_level: u32 = risk_map.values().sum();
 
-	let mut basins: Vec<(CoordSet, usize)> = map.basins(&min_coords).into_iter()
-		.map(|basin| {
-			let len = basin.len();
-			(basin, len)
-		})
-		.collect();
-	basins.sort_by_key(|basin| basin.1);
-	let top_basin_product: usize = basins.iter().rev().map(|b| b.1).take(3).reduce(|c, b| c * b).unwrap_or(0);
+	let basins = map.basins(&min_coords);
+	let top = top_basins(&basins, 3);
+	let top_basin_product: usize = top.iter().map(|b| b.size()).reduce(|c, b| c * b).unwrap_or(0);
 
-	basins.iter().rev().take(3).for_each(|b| println!("--- Basin: {} {}", b.1, map.highlight(&b.0)));
+	top.iter().for_each(|b| {
+		let cells: CoordSet = b.cells.iter().copied().collect();
+		println!("--- Basin: {} at {:?} {}", b.size(), b.low, map.highlight(&cells));
+	});
 	println!("{}", map.highlight(&min_coords));
 	println!("Risk level: {}", risk_level);
 	println!("Top 3 basin product: {}", top_basin_product);